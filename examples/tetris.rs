@@ -1,6 +1,6 @@
 use chip8::Chip8;
 
-pub fn main() {
+pub fn main() -> chip8::Result<()> {
     let mut chip8 = Chip8::new(16);
-    chip8.run("roms/TETRIS");
+    chip8.run("roms/TETRIS")
 }
@@ -1,16 +1,281 @@
-use clap::Parser;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use crate::audio::{Tone, Waveform};
+use crate::config::Config;
+use crate::keymap::{Keymap, Layout};
+use crate::palette::{Palette, Theme};
+use crate::quirks::QuirksProfile;
+use crate::rewind::Rewind;
+use crate::scheduler::Speed;
+#[cfg(feature = "sdl")]
+use crate::WindowOptions;
 
 /// CHIP8 emulator
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// ROM to play
-    #[arg()]
-    pub rom: String,
+    #[arg(required = true)]
+    pub rom: Option<String>,
+
+    /// Configuration file, instead of `$XDG_CONFIG_HOME/chip8/config.toml`
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Frontend showing the screen and reading the keypad
+    #[arg(long, value_enum, default_value_t = Frontend::Sdl)]
+    pub frontend: Frontend,
 
     /// Screen scale multiplier
     #[arg(long, default_value_t = 16)]
     pub scale: u8,
+
+    /// Colour theme
+    #[arg(long, value_enum, default_value_t = Theme::Classic)]
+    pub theme: Theme,
+
+    /// Custom colours overriding the theme: `background,foreground` and, for
+    /// XO-CHIP, the colours of the second plane and of both planes, e.g.
+    /// `#000000,#FFFFFF,#AAAAAA,#555555`
+    #[arg(long)]
+    pub palette: Option<Palette>,
+
+    /// Start in fullscreen mode, toggled with F11
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Synchronise the window with the display refresh rate
+    #[arg(long)]
+    pub vsync: bool,
+
+    /// Keys bound to the keypad: a preset (`default` or `keypad`) and
+    /// bindings such as `1=W,4=S,C=Up,D=Down`. A `<ROM>.keymap` file next to
+    /// the ROM overrides it
+    #[arg(long, default_value_t = Keymap::default(), hide_default_value = true)]
+    pub keymap: Keymap,
+
+    /// Keyboard layout, to read the keys in the terminal
+    #[arg(long, value_enum, default_value_t = Layout::Qwerty)]
+    pub layout: Layout,
+
+    /// Milliseconds a key stays held down in the terminal until it repeats,
+    /// longer than the auto-repeat delay of the keyboard
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    pub release_delay: u64,
+
+    /// Quirks profile of the interpreter the ROM was written for, by default
+    /// that of the ROM database, or modern
+    #[arg(long, value_enum)]
+    pub quirks: Option<QuirksProfile>,
+
+    /// Instructions executed per frame, overriding --cps
+    #[arg(long, conflicts_with = "cps")]
+    pub ipf: Option<u32>,
+
+    /// Instructions executed per second, by default the tickrate of the ROM
+    /// database, or 600
+    #[arg(long)]
+    pub cps: Option<u32>,
+
+    /// Frames per second, polling the keyboard and refreshing the screen
+    #[arg(long, default_value_t = 60)]
+    pub fps: u32,
+
+    /// Buzzer frequency in Hz
+    #[arg(long, default_value_t = 440.0, value_parser = parse_frequency)]
+    pub frequency: f32,
+
+    /// Buzzer volume, from 0 to 1
+    #[arg(long, default_value_t = 0.25, value_parser = parse_volume)]
+    pub volume: f32,
+
+    /// Buzzer waveform
+    #[arg(long, value_enum, default_value_t = Waveform::Square)]
+    pub waveform: Waveform,
+
+    /// Seed of the random number generator, random if unset
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Record the keyboard input into a movie file
+    #[arg(long, conflicts_with = "replay")]
+    pub record: Option<String>,
+
+    /// Play back a movie file recorded with --record
+    #[arg(long)]
+    pub replay: Option<String>,
+
+    /// Play back the movie without a window, printing the final screen
+    #[arg(long, requires = "replay")]
+    pub headless: bool,
+
+    /// Start paused in the interactive debugger
+    #[arg(long)]
+    pub debug: bool,
+
+    /// Frames kept to rewind with backspace, 0 to disable rewinding
+    #[arg(long, default_value_t = 600)]
+    pub rewind_frames: usize,
+
+    /// Memory used by the rewind frames, in MiB
+    #[arg(long, default_value_t = 64)]
+    pub rewind_memory: usize,
+}
+
+/// Where the emulator is played.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frontend {
+    /// SDL2 window
+    Sdl,
+    /// Terminal, drawing with Unicode half blocks
+    Tui,
+}
+
+/// Tools working on ROMs instead of playing them.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print an assembly listing of the ROM
+    Disasm {
+        /// ROM to disassemble
+        rom: String,
+    },
+    /// Print the metadata of the ROM found in the ROM database
+    Info {
+        /// ROM to describe
+        rom: String,
+    },
+    /// Assemble a source file into a ROM
+    Asm {
+        /// Source file
+        source: String,
+        /// ROM to write, defaults to the source file with the `ch8` extension
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+impl Args {
+    /// Parse the command line, the settings of the configuration file serving
+    /// as defaults of the options.
+    pub fn load() -> crate::Result<Args> {
+        let argv: Vec<OsString> = env::args_os().collect();
+        let args = Args::parse_from(&argv);
+        let config = match &args.config {
+            Some(path) => Config::load(path)?,
+            None => match Config::default_path() {
+                Some(path) if path.exists() => Config::load(path)?,
+                _ => Config::default(),
+            },
+        };
+        Ok(Args::try_parse_with_config(&argv, &config).unwrap_or_else(|err| err.exit()))
+    }
+
+    /// Parse the given command line with the settings of the configuration,
+    /// including those of the ROM, as defaults of the options.
+    pub fn try_parse_with_config<I, T>(argv: I, config: &Config) -> Result<Args, clap::Error>
+    where
+        I: IntoIterator<Item = T> + Clone,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Args::command().try_get_matches_from(argv.clone())?;
+        let rom = matches
+            .get_one::<String>("rom")
+            .and_then(|rom| fs::read(rom).ok());
+
+        let mut command = Args::command();
+        for (long, value) in config.settings(rom.as_deref()) {
+            // --ipf takes precedence over --cps, which must win when on the command line
+            if long == "ipf" && matches.value_source("cps") == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            let id = long.replace('-', "_");
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == id.as_str());
+            if !arg.is_some_and(|arg| is_valid(arg, &value)) {
+                return Err(command.error(
+                    ErrorKind::InvalidValue,
+                    format!(
+                        "invalid value '{}' for '{}' in the configuration",
+                        value, long
+                    ),
+                ));
+            }
+            command = command.mut_arg(id, |arg| arg.default_value(value));
+        }
+        Args::from_arg_matches(&command.try_get_matches_from(argv)?)
+    }
+
+    /// Speed set by --ipf or --cps, if any.
+    pub fn speed(&self) -> Option<Speed> {
+        match (self.ipf, self.cps) {
+            (Some(ipf), _) => Some(Speed::new(ipf, self.fps)),
+            (None, Some(cps)) => Some(Speed::from_cycles_per_second(cps, self.fps)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn rewind(&self) -> Option<Rewind> {
+        (self.rewind_frames > 0).then(|| Rewind::new(self.rewind_frames, self.rewind_memory << 20))
+    }
+
+    #[cfg(feature = "sdl")]
+    pub fn window(&self) -> WindowOptions {
+        WindowOptions {
+            scale: self.scale,
+            fullscreen: self.fullscreen,
+            vsync: self.vsync,
+        }
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette.unwrap_or_else(|| self.theme.palette())
+    }
+
+    pub fn tone(&self) -> Tone {
+        Tone {
+            frequency: self.frequency,
+            volume: self.volume,
+            waveform: self.waveform,
+        }
+    }
+}
+
+/// Whether the option accepts the value, as a flag or on the command line.
+fn is_valid(arg: &Arg, value: &str) -> bool {
+    let arg = Arg::new("value")
+        .action(ArgAction::Set)
+        .allow_hyphen_values(true)
+        .value_parser(arg.get_value_parser().clone());
+    clap::Command::new("chip8")
+        .no_binary_name(true)
+        .arg(arg)
+        .try_get_matches_from([value])
+        .is_ok()
+}
+
+fn parse_frequency(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(frequency) if frequency.is_finite() && frequency > 0.0 => Ok(frequency),
+        _ => Err("must be a positive number of Hz".to_string()),
+    }
+}
+
+fn parse_volume(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(volume) if (0.0..=1.0).contains(&volume) => Ok(volume),
+        _ => Err("must be a number from 0 to 1".to_string()),
+    }
 }
 
 #[test]
@@ -18,3 +283,113 @@ fn verify_cli() {
     use clap::CommandFactory;
     Args::command().debug_assert()
 }
+
+#[test]
+fn speed_from_ipf() {
+    let args = Args::parse_from(["chip8", "ROM", "--ipf", "20"]);
+    assert_eq!(args.speed(), Some(Speed::new(20, 60)));
+
+    let args = Args::parse_from(["chip8", "ROM", "--cps", "300", "--fps", "30"]);
+    assert_eq!(args.speed(), Some(Speed::new(10, 30)));
+
+    let args = Args::parse_from(["chip8", "ROM", "--fps", "30"]);
+    assert_eq!(args.speed(), None);
+}
+
+#[test]
+fn tone_range() {
+    let args = Args::parse_from(["chip8", "ROM", "--frequency", "261.6", "--volume", "1"]);
+    assert_eq!((args.tone().frequency, args.tone().volume), (261.6, 1.0));
+
+    for invalid in [
+        "--frequency=-440",
+        "--frequency=0",
+        "--frequency=NaN",
+        "--frequency=inf",
+        "--volume=2",
+        "--volume=-0.5",
+        "--volume=NaN",
+    ] {
+        let err = Args::try_parse_from(["chip8", "ROM", invalid]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueValidation, "{}", invalid);
+    }
+}
+
+#[test]
+fn disasm_subcommand() {
+    let args = Args::parse_from(["chip8", "disasm", "ROM"]);
+    assert!(matches!(args.command, Some(Command::Disasm { rom }) if rom == "ROM"));
+    assert_eq!(args.rom, None);
+
+    let args = Args::parse_from(["chip8", "ROM"]);
+    assert!(args.command.is_none());
+    assert_eq!(args.rom.as_deref(), Some("ROM"));
+
+    assert!(Args::try_parse_from(["chip8"]).is_err());
+
+    let args = Args::parse_from(["chip8", "info", "ROM"]);
+    assert!(matches!(args.command, Some(Command::Info { rom }) if rom == "ROM"));
+}
+
+#[test]
+fn palette_overrides_theme() {
+    let args = Args::parse_from(["chip8", "ROM", "--theme", "octo"]);
+    assert_eq!(args.palette(), Theme::Octo.palette());
+
+    let args = Args::parse_from([
+        "chip8",
+        "ROM",
+        "--theme",
+        "octo",
+        "--palette",
+        "#000080,#FFFF00",
+    ]);
+    assert_eq!(args.palette().foreground().to_string(), "#FFFF00");
+}
+
+#[test]
+fn config_defaults() {
+    let rom = concat!(env!("CARGO_MANIFEST_DIR"), "/roms/INVADERS");
+    let config: Config = format!(
+        "scale = 8\nipf = 20\ntheme = \"amber\"\nvsync = true\n\
+         [rom.{}]\nquirks = \"cosmac-vip\"\nipf = 30",
+        crate::config::sha1(&fs::read(rom).unwrap())
+    )
+    .parse()
+    .unwrap();
+
+    let args = Args::try_parse_with_config(["chip8", "other.ch8"], &config).unwrap();
+    assert_eq!(args.scale, 8);
+    assert_eq!(args.theme, Theme::Amber);
+    assert!(args.vsync);
+    assert_eq!(args.quirks, None);
+    assert_eq!(args.speed(), Some(Speed::new(20, 60)));
+
+    let args = Args::try_parse_with_config(["chip8", rom, "--scale", "4"], &config).unwrap();
+    assert_eq!(args.scale, 4);
+    assert_eq!(args.quirks, Some(QuirksProfile::CosmacVip));
+    assert_eq!(args.speed(), Some(Speed::new(30, 60)));
+
+    let args = Args::try_parse_with_config(["chip8", rom, "--cps", "300"], &config).unwrap();
+    assert_eq!(args.speed(), Some(Speed::new(5, 60)));
+
+    for invalid in ["scale = \"big\"", "vsync = 1", "theme = \"pink\""] {
+        let config: Config = invalid.parse().unwrap();
+        let err = Args::try_parse_with_config(["chip8", rom], &config).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidValue);
+    }
+}
+
+#[test]
+fn every_setting_is_an_option() {
+    let command = Args::command();
+    for setting in crate::config::SETTINGS {
+        assert!(
+            command
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some(setting)),
+            "{}",
+            setting
+        );
+    }
+}
@@ -0,0 +1,494 @@
+//! Assembler building ROMs from the mnemonics printed by the disassembler.
+//!
+//! Each line holds one statement, `;` starting a comment:
+//!
+//! ```text
+//! SPEED equ 2             ; constant
+//! start:                  ; label, the address of the next statement
+//!     LD V0, SPEED
+//!     LD I, sprite
+//!     DRW V0, V0, 2
+//!     JP start
+//! sprite:
+//!     db 0b11110000, 0x90 ; bytes
+//!     dw 0x1234           ; big-endian words
+//! include "font.asm"      ; another file, relative to this one
+//! ```
+//!
+//! Numbers are decimal, or hexadecimal and binary with the `0x` and `0b`
+//! prefixes. Labels and constants can be used before being defined, and values
+//! can be summed as in `sprite + 5`. The program is assembled at 0x200.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::cpu::instructions::Instruction;
+use crate::cpu::ROM_START;
+use crate::error::{Chip8Error, Result};
+
+/// Nesting limit of includes and constants, catching cycles.
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone)]
+struct Location {
+    file: String,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, message: String) -> Chip8Error {
+        Chip8Error::Assembly {
+            file: self.file.clone(),
+            line: self.line,
+            message,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Operand {
+    V(u8),
+    I,
+    /// `[I]`, the memory pointed to by I
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    /// `LONG nnnn`, the 16-bit address of XO-CHIP's `LD I, LONG`
+    Long(String),
+    Value(String),
+}
+
+impl Operand {
+    fn parse(s: &str) -> Operand {
+        let upper = s.to_uppercase();
+        match upper.as_str() {
+            "I" => return Operand::I,
+            "[I]" => return Operand::IndirectI,
+            "DT" => return Operand::Dt,
+            "ST" => return Operand::St,
+            "K" => return Operand::K,
+            "F" => return Operand::F,
+            "HF" => return Operand::Hf,
+            "B" => return Operand::B,
+            "R" => return Operand::R,
+            _ => (),
+        }
+        if let Some(x) = upper.strip_prefix('V') {
+            if let (1, Ok(x)) = (x.len(), u8::from_str_radix(x, 16)) {
+                return Operand::V(x);
+            }
+        }
+        if upper.starts_with("LONG ") {
+            return Operand::Long(s[5..].trim().to_string());
+        }
+        Operand::Value(s.to_string())
+    }
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction {
+        text: String,
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+#[derive(Debug)]
+enum Symbol {
+    Address(u16),
+    Constant(String),
+}
+
+#[derive(Default)]
+struct Assembler {
+    statements: Vec<(Location, Statement)>,
+    symbols: HashMap<String, Symbol>,
+    /// Address of the next statement
+    address: usize,
+}
+
+impl Assembler {
+    /// First pass: collect the statements and the symbols, following the includes.
+    fn parse(&mut self, file: &str, dir: &Path, source: &str, depth: usize) -> Result<()> {
+        for (number, line) in source.lines().enumerate() {
+            let location = Location {
+                file: file.to_string(),
+                line: number + 1,
+            };
+            let mut line = line.split(';').next().unwrap_or_default().trim();
+
+            let first = line.split_whitespace().next().unwrap_or_default();
+            if let Some(label) = first.strip_suffix(':') {
+                self.define(&location, label, Symbol::Address(self.address as u16))?;
+                line = line[first.len()..].trim();
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let (keyword, args) = split_word(line);
+            let (second, value) = split_word(args);
+            if second.eq_ignore_ascii_case("equ") {
+                self.define(&location, keyword, Symbol::Constant(value.to_string()))?;
+                continue;
+            }
+
+            let statement = match keyword.to_lowercase().as_str() {
+                "include" => {
+                    if depth >= MAX_DEPTH {
+                        return Err(location.error("includes nested too deeply".to_string()));
+                    }
+                    let path = dir.join(args.trim_matches('"'));
+                    let source = fs::read_to_string(&path).map_err(|err| {
+                        location.error(format!("cannot include {}: {}", path.display(), err))
+                    })?;
+                    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                    self.parse(&path.display().to_string(), dir, &source, depth + 1)?;
+                    continue;
+                }
+                "db" => Statement::Bytes(split_operands(args)),
+                "dw" => Statement::Words(split_operands(args)),
+                _ => Statement::Instruction {
+                    text: line.to_string(),
+                    mnemonic: keyword.to_uppercase(),
+                    operands: split_operands(args)
+                        .iter()
+                        .map(|operand| Operand::parse(operand))
+                        .collect(),
+                },
+            };
+
+            self.address += match &statement {
+                Statement::Bytes(values) => values.len(),
+                Statement::Words(values) => values.len() * 2,
+                Statement::Instruction { operands, .. } => {
+                    if operands
+                        .iter()
+                        .any(|operand| matches!(operand, Operand::Long(_)))
+                    {
+                        4
+                    } else {
+                        2
+                    }
+                }
+            };
+            self.statements.push((location, statement));
+        }
+
+        Ok(())
+    }
+
+    fn define(&mut self, location: &Location, name: &str, symbol: Symbol) -> Result<()> {
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(location.error(format!("invalid symbol name `{}`", name)));
+        }
+        if self.symbols.insert(name.to_string(), symbol).is_some() {
+            return Err(location.error(format!("duplicate symbol `{}`", name)));
+        }
+        Ok(())
+    }
+
+    /// Second pass: encode the statements with every symbol known.
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut rom = vec![];
+
+        for (location, statement) in self.statements.iter() {
+            match statement {
+                Statement::Bytes(values) => {
+                    for value in values {
+                        rom.push(self.value(location, value, 8)? as u8);
+                    }
+                }
+                Statement::Words(values) => {
+                    for value in values {
+                        rom.extend(self.value(location, value, 16)?.to_be_bytes());
+                    }
+                }
+                Statement::Instruction {
+                    text,
+                    mnemonic,
+                    operands,
+                } => {
+                    let (instruction, long) = self
+                        .instruction(location, mnemonic, operands)?
+                        .ok_or_else(|| location.error(format!("invalid instruction `{}`", text)))?;
+                    rom.extend(instruction.encode().to_be_bytes());
+                    if let Some(long) = long {
+                        rom.extend(long.to_be_bytes());
+                    }
+                }
+            }
+        }
+
+        Ok(rom)
+    }
+
+    /// Build the instruction, with the address following `LD I, LONG`.
+    /// Return `None` if the operands do not match the mnemonic.
+    fn instruction(
+        &self,
+        location: &Location,
+        mnemonic: &str,
+        operands: &[Operand],
+    ) -> Result<Option<(Instruction, Option<u16>)>> {
+        use Operand::*;
+
+        let nnn = |value: &str| self.value(location, value, 12);
+        let kk = |value: &str| self.value(location, value, 8).map(|kk| kk as u8);
+        let n = |value: &str| self.value(location, value, 4).map(|n| n as u8);
+
+        let instruction = match (mnemonic, operands) {
+            ("SYS", [Value(a)]) => Instruction::Sys { nnn: nnn(a)? },
+            ("CLS", []) => Instruction::Cls,
+            ("RET", []) => Instruction::Rts,
+            ("SCD", [Value(v)]) => Instruction::Scd { n: n(v)? },
+            ("SCU", [Value(v)]) => Instruction::Scu { n: n(v)? },
+            ("SCR", []) => Instruction::Scr,
+            ("SCL", []) => Instruction::Scl,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::Low,
+            ("HIGH", []) => Instruction::High,
+            ("JP", [Value(a)]) => Instruction::Jmp { nnn: nnn(a)? },
+            ("JP", [V(0), Value(a)]) => Instruction::Jumpi { nnn: nnn(a)? },
+            ("CALL", [Value(a)]) => Instruction::Call { nnn: nnn(a)? },
+            ("SE", [V(x), Value(v)]) => Instruction::Ske { x: *x, kk: kk(v)? },
+            ("SE", [V(x), V(y)]) => Instruction::Skre { x: *x, y: *y },
+            ("SNE", [V(x), Value(v)]) => Instruction::Skne { x: *x, kk: kk(v)? },
+            ("SNE", [V(x), V(y)]) => Instruction::Skrne { x: *x, y: *y },
+            ("SAVE", [V(x), V(y)]) => Instruction::Srng { x: *x, y: *y },
+            ("LOAD", [V(x), V(y)]) => Instruction::Lrng { x: *x, y: *y },
+            ("LD", [V(x), Value(v)]) => Instruction::Load { x: *x, kk: kk(v)? },
+            ("LD", [V(x), V(y)]) => Instruction::Move { x: *x, y: *y },
+            ("LD", [I, Value(a)]) => Instruction::Loadi { nnn: nnn(a)? },
+            ("LD", [I, Long(a)]) => {
+                let long = self.value(location, a, 16)?;
+                return Ok(Some((Instruction::Ldil, Some(long))));
+            }
+            ("LD", [V(x), Dt]) => Instruction::Moved { x: *x },
+            ("LD", [V(x), K]) => Instruction::Keyd { x: *x },
+            ("LD", [Dt, V(x)]) => Instruction::Loadd { x: *x },
+            ("LD", [St, V(x)]) => Instruction::Loads { x: *x },
+            ("LD", [F, V(x)]) => Instruction::Ldspr { x: *x },
+            ("LD", [Hf, V(x)]) => Instruction::Ldhf { x: *x },
+            ("LD", [B, V(x)]) => Instruction::Bcd { x: *x },
+            ("LD", [IndirectI, V(x)]) => Instruction::Stor { x: *x },
+            ("LD", [V(x), IndirectI]) => Instruction::Read { x: *x },
+            ("LD", [R, V(x)]) => Instruction::Srpl { x: *x },
+            ("LD", [V(x), R]) => Instruction::Lrpl { x: *x },
+            ("ADD", [V(x), Value(v)]) => Instruction::Add { x: *x, kk: kk(v)? },
+            ("ADD", [V(x), V(y)]) => Instruction::Addr { x: *x, y: *y },
+            ("ADD", [I, V(x)]) => Instruction::Addi { x: *x },
+            ("OR", [V(x), V(y)]) => Instruction::Or { x: *x, y: *y },
+            ("AND", [V(x), V(y)]) => Instruction::And { x: *x, y: *y },
+            ("XOR", [V(x), V(y)]) => Instruction::Xor { x: *x, y: *y },
+            ("SUB", [V(x), V(y)]) => Instruction::Sub { x: *x, y: *y },
+            ("SUBN", [V(x), V(y)]) => Instruction::Subn { x: *x, y: *y },
+            ("SHR", [V(x)]) => Instruction::Shr { x: *x, y: *x },
+            ("SHR", [V(x), V(y)]) => Instruction::Shr { x: *x, y: *y },
+            ("SHL", [V(x)]) => Instruction::Shl { x: *x, y: *x },
+            ("SHL", [V(x), V(y)]) => Instruction::Shl { x: *x, y: *y },
+            ("RND", [V(x), Value(v)]) => Instruction::Rand { x: *x, kk: kk(v)? },
+            ("DRW", [V(x), V(y), Value(v)]) => Instruction::Draw {
+                x: *x,
+                y: *y,
+                n: n(v)?,
+            },
+            ("SKP", [V(x)]) => Instruction::Skpr { x: *x },
+            ("SKNP", [V(x)]) => Instruction::Skup { x: *x },
+            ("PLANE", [Value(v)]) => Instruction::Plane { n: n(v)? },
+            ("AUDIO", []) => Instruction::Audio,
+            ("PITCH", [V(x)]) => Instruction::Pitch { x: *x },
+            _ => return Ok(None),
+        };
+
+        Ok(Some((instruction, None)))
+    }
+
+    /// Evaluate a sum of numbers and symbols, checking it fits in `bits`.
+    fn value(&self, location: &Location, value: &str, bits: u32) -> Result<u16> {
+        let sum = self.evaluate(location, value, 0)?;
+        if sum >= 1 << bits {
+            return Err(location.error(format!(
+                "value {:#X} of `{}` does not fit in {} bits",
+                sum, value, bits
+            )));
+        }
+        Ok(sum as u16)
+    }
+
+    fn evaluate(&self, location: &Location, value: &str, depth: usize) -> Result<u32> {
+        let mut sum: u32 = 0;
+        for term in value.split('+').map(str::trim) {
+            let term = match (parse_number(term), self.symbols.get(term)) {
+                (Some(number), _) => number,
+                (None, Some(Symbol::Address(address))) => *address as u32,
+                (None, Some(Symbol::Constant(constant))) if depth < MAX_DEPTH => {
+                    self.evaluate(location, constant, depth + 1)?
+                }
+                (None, Some(Symbol::Constant(_))) => {
+                    return Err(location.error(format!("recursive constant `{}`", term)))
+                }
+                (None, None) if term.is_empty() => {
+                    return Err(location.error("missing value".to_string()))
+                }
+                (None, None) => return Err(location.error(format!("unknown symbol `{}`", term))),
+            };
+            sum = sum.saturating_add(term);
+        }
+        Ok(sum)
+    }
+}
+
+/// Split the first word from the rest of the line.
+fn split_word(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], line[end..].trim()),
+        None => (line, ""),
+    }
+}
+
+fn split_operands(args: &str) -> Vec<String> {
+    if args.is_empty() {
+        return vec![];
+    }
+    args.split(',').map(|arg| arg.trim().to_string()).collect()
+}
+
+fn parse_number(s: &str) -> Option<u32> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = s.strip_prefix("0b").or_else(|| s.strip_prefix("0B")) {
+        u32::from_str_radix(binary, 2).ok()
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn assemble_source(file: &str, dir: &Path, source: &str) -> Result<Vec<u8>> {
+    let mut assembler = Assembler {
+        address: ROM_START,
+        ..Default::default()
+    };
+    assembler.parse(file, dir, source, 0)?;
+    assembler.encode()
+}
+
+/// Assemble a program, resolving includes from the current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>> {
+    assemble_source("<input>", Path::new(""), source)
+}
+
+/// Assemble a source file, resolving includes relative to its directory.
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    assemble_source(&path.display().to_string(), dir, &source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Cpu;
+    use crate::disasm::disassemble;
+    use crate::keyboard::KeyState;
+
+    fn assert_error(source: &str, line: usize, expected: &str) {
+        match assemble(source) {
+            Err(Chip8Error::Assembly {
+                line: l, message, ..
+            }) => {
+                assert_eq!(l, line);
+                assert!(message.contains(expected), "{}", message);
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn assemble_program() {
+        let source = "
+            COUNT equ 3       ; constant
+            start:
+                LD V0, COUNT
+                LD I, sprite
+                DRW V0, V1, 2
+            loop: JP loop
+            sprite:
+                db 0b11110000, 0x90
+                dw sprite + 1
+        ";
+
+        let rom = assemble(source).unwrap();
+
+        assert_eq!(
+            rom,
+            [0x60, 0x03, 0xA2, 0x08, 0xD0, 0x12, 0x12, 0x06, 0xF0, 0x90, 0x02, 0x09]
+        );
+
+        let mut cpu = Cpu::default();
+        cpu.load_rom(&rom).unwrap();
+        for _ in 0..3 {
+            cpu.tick(&KeyState::new()).unwrap();
+        }
+        assert_eq!(cpu.v()[0], 3);
+        assert_eq!(cpu.i(), 0x208);
+        assert!(cpu.vram().get(3, 0));
+    }
+
+    #[test]
+    fn long_load() {
+        let rom = assemble("LD I, LONG data\nEXIT\ndata: db 1").unwrap();
+
+        assert_eq!(rom, [0xF0, 0x00, 0x02, 0x06, 0x00, 0xFD, 0x01]);
+    }
+
+    #[test]
+    fn errors_report_line() {
+        assert_error("CLS\n\nLD V0, 0x100", 3, "does not fit in 8 bits");
+        assert_error("JP nowhere", 1, "unknown symbol `nowhere`");
+        assert_error("CLS\nLD V0", 2, "invalid instruction `LD V0`");
+        assert_error("a: CLS\na: CLS", 2, "duplicate symbol `a`");
+        assert_error("A equ B\nB equ A\nLD V0, A", 3, "recursive constant");
+        assert_error("include \"missing.asm\"", 1, "cannot include");
+    }
+
+    #[test]
+    fn include_relative_to_file() {
+        let dir = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.asm"), "CALL sub\ninclude \"lib/sub.asm\"").unwrap();
+        fs::write(dir.join("lib/sub.asm"), "sub: RET").unwrap();
+
+        let rom = assemble_file(dir.join("main.asm"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(rom.unwrap(), [0x22, 0x02, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn reassemble_disassembly() {
+        for entry in fs::read_dir("roms").unwrap() {
+            let path = entry.unwrap().path();
+            let rom = fs::read(&path).unwrap();
+
+            let reassembled = assemble(&disassemble(&rom).unwrap()).unwrap();
+
+            assert!(reassembled == rom, "{} differs", path.display());
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Buzzer sound generation, shared by the audio backends.
+
+use std::f32::consts::PI;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::backend::{pattern_rate, Audio};
+
+/// Shape of the buzzer tone.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+/// Buzzer tone played while the sound timer is active.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// Frequency in Hz
+    pub frequency: f32,
+    /// Volume from 0 to 1
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for Tone {
+    fn default() -> Tone {
+        Tone {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// Generates the buzzer samples: the configured tone or, once an XO-CHIP
+/// program loaded one, the audio pattern.
+#[derive(Debug, Clone)]
+pub struct Oscillator {
+    tone: Tone,
+    sample_rate: u32,
+    playing: bool,
+    pattern: Option<([u8; 16], f32)>,
+    /// Position within the current period, from 0 to 1
+    phase: f32,
+}
+
+impl Oscillator {
+    pub fn new(tone: Tone, sample_rate: u32) -> Oscillator {
+        Oscillator {
+            tone,
+            sample_rate,
+            playing: false,
+            pattern: None,
+            phase: 0.0,
+        }
+    }
+
+    pub fn tone(&self) -> Tone {
+        self.tone
+    }
+
+    pub fn set_tone(&mut self, tone: Tone) {
+        self.tone = tone;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    pub fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        self.pattern = Some((*pattern, pattern_rate(pitch)));
+    }
+
+    /// Fill `out` with the next samples, from -volume to volume.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.playing { self.next() } else { 0.0 };
+        }
+    }
+
+    fn next(&mut self) -> f32 {
+        let volume = self.tone.volume;
+        let (value, step) = match &self.pattern {
+            Some((pattern, rate)) => {
+                // 128 1-bit samples played at `rate`
+                let bit = (self.phase * 128.0) as usize % 128;
+                let on = (pattern[bit / 8] >> (7 - bit % 8)) & 1 != 0;
+                let value = if on { volume } else { -volume };
+                (value, rate / 128.0 / self.sample_rate as f32)
+            }
+            None => {
+                let phase = self.phase;
+                let value = match self.tone.waveform {
+                    Waveform::Square if phase < 0.5 => volume,
+                    Waveform::Square => -volume,
+                    Waveform::Sine => volume * (2.0 * PI * phase).sin(),
+                    Waveform::Triangle => volume * (1.0 - 4.0 * (phase - 0.5).abs()),
+                    Waveform::Sawtooth => volume * (2.0 * phase - 1.0),
+                };
+                (value, self.tone.frequency / self.sample_rate as f32)
+            }
+        };
+        self.phase = (self.phase + step).fract();
+        value
+    }
+}
+
+/// Audio backend recording the samples in memory, to test or export the sound.
+#[derive(Debug, Clone)]
+pub struct BufferAudio {
+    oscillator: Oscillator,
+    samples: Vec<f32>,
+}
+
+impl BufferAudio {
+    pub fn new(tone: Tone, sample_rate: u32) -> BufferAudio {
+        BufferAudio {
+            oscillator: Oscillator::new(tone, sample_rate),
+            samples: vec![],
+        }
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// Write the recorded samples as a 16-bit mono WAV file.
+    pub fn write_wav<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let sample_rate = self.oscillator.sample_rate();
+        let data_len = (self.samples.len() * 2) as u32;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_len).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16_u32.to_le_bytes())?; // chunk size
+        writer.write_all(&1_u16.to_le_bytes())?; // PCM
+        writer.write_all(&1_u16.to_le_bytes())?; // mono
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * 2).to_le_bytes())?; // bytes per second
+        writer.write_all(&2_u16.to_le_bytes())?; // bytes per frame
+        writer.write_all(&16_u16.to_le_bytes())?; // bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&data_len.to_le_bytes())?;
+        for sample in self.samples.iter() {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for BufferAudio {
+    fn default() -> BufferAudio {
+        BufferAudio::new(Tone::default(), 44100)
+    }
+}
+
+impl Audio for BufferAudio {
+    fn set_playing(&mut self, playing: bool) {
+        self.oscillator.set_playing(playing);
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        self.oscillator.set_pattern(pattern, pitch);
+    }
+
+    fn advance(&mut self, duration: Duration) {
+        let len = (duration.as_secs_f64() * self.oscillator.sample_rate() as f64).round();
+        let start = self.samples.len();
+        self.samples.resize(start + len as usize, 0.0);
+        self.oscillator.fill(&mut self.samples[start..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_wave() {
+        let tone = Tone {
+            frequency: 1000.0,
+            volume: 0.5,
+            waveform: Waveform::Square,
+        };
+        let mut oscillator = Oscillator::new(tone, 8000);
+        let mut samples = [1.0; 8];
+
+        oscillator.fill(&mut samples);
+        assert_eq!(samples, [0.0; 8], "Silent until playing");
+
+        oscillator.set_playing(true);
+        oscillator.fill(&mut samples);
+        assert_eq!(samples, [0.5, 0.5, 0.5, 0.5, -0.5, -0.5, -0.5, -0.5]);
+    }
+
+    #[test]
+    fn pattern() {
+        let mut oscillator = Oscillator::new(Tone::default(), 4000);
+        oscillator.set_playing(true);
+        // 4000 samples per second at pitch 64, as the sample rate
+        oscillator.set_pattern(&[0xF0; 16], 64);
+        let mut samples = [0.0; 8];
+
+        oscillator.fill(&mut samples);
+
+        assert_eq!(
+            samples.map(|sample| sample > 0.0),
+            [true, true, true, true, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn buffer_wav() {
+        let mut audio = BufferAudio::new(Tone::default(), 1000);
+        audio.set_playing(true);
+        audio.advance(Duration::from_millis(100));
+        assert_eq!(audio.samples().len(), 100);
+        assert!(audio.samples().iter().all(|sample| *sample != 0.0));
+
+        let mut wav = vec![];
+        audio.write_wav(&mut wav).unwrap();
+
+        assert_eq!(wav.len(), 44 + 200);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[40..44], &200_u32.to_le_bytes());
+    }
+}
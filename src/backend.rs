@@ -0,0 +1,139 @@
+//! Traits implemented by the frontends driving a [`Machine`](crate::Machine).
+//!
+//! A frontend provides a way to show the screen, read the keypad, play the
+//! buzzer and measure time. The SDL2 window is one implementation; the `Null*`
+//! types below allow running the emulator without any of them.
+
+use std::time::{Duration, Instant};
+
+use crate::keyboard::KeyEvent;
+use crate::keymap::Keymap;
+use crate::palette::Palette;
+use crate::vram::Vram;
+
+/// Somewhere to render the CHIP8 video memory.
+pub trait Display {
+    /// Render the given frame. Only called when the VRAM has changed, or
+    /// when [`needs_redraw`](Display::needs_redraw) returns `true`.
+    fn draw(&mut self, vram: &Vram);
+
+    /// Whether the frame must be drawn again although the VRAM has not
+    /// changed, e.g. because the window was resized. Called every frame.
+    fn needs_redraw(&mut self) -> bool {
+        false
+    }
+
+    /// Switch between windowed and fullscreen mode, if supported.
+    fn toggle_fullscreen(&mut self) {}
+
+    /// Change the colours of the screen, from the next frame drawn.
+    fn set_palette(&mut self, _palette: Palette) {}
+}
+
+/// Source of keypad events.
+pub trait Input {
+    /// Return key presses and releases since the last call.
+    ///
+    /// Pressing [`Key::Exit`](crate::Key::Exit) stops the machine.
+    fn poll(&mut self) -> Vec<KeyEvent>;
+
+    /// Change the keys bound to the keypad, if they can be remapped.
+    fn set_keymap(&mut self, _keymap: &Keymap) {}
+}
+
+/// Buzzer driven by the sound timer.
+pub trait Audio {
+    /// Called every frame with whether the buzzer must be sounding.
+    fn set_playing(&mut self, playing: bool);
+
+    /// Called at the end of every frame with the emulated time it lasted, for
+    /// backends generating the samples themselves rather than in real time.
+    fn advance(&mut self, _duration: Duration) {}
+
+    /// Called when an XO-CHIP program loads an audio pattern or changes the pitch.
+    ///
+    /// The pattern is a 128 samples 1-bit waveform, played back at [`pattern_rate`].
+    fn set_pattern(&mut self, _pattern: &[u8; 16], _pitch: u8) {}
+}
+
+/// Playback rate of an XO-CHIP audio pattern, in samples per second.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2_f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+/// Monotonic time source used to pace the emulation.
+pub trait Clock {
+    /// Time elapsed since an arbitrary, fixed starting point.
+    fn now(&self) -> Duration;
+
+    /// Wait for the given duration before running the next frame.
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// Display discarding every frame.
+#[derive(Debug, Default)]
+pub struct NullDisplay;
+
+impl Display for NullDisplay {
+    fn draw(&mut self, _vram: &Vram) {}
+}
+
+/// Input never pressing any key.
+#[derive(Debug, Default)]
+pub struct NullInput;
+
+impl Input for NullInput {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        vec![]
+    }
+}
+
+/// Silent audio.
+#[derive(Debug, Default)]
+pub struct NullAudio;
+
+impl Audio for NullAudio {
+    fn set_playing(&mut self, _playing: bool) {}
+}
+
+/// Virtual clock that never waits, running the emulation as fast as possible.
+///
+/// Time only advances when sleeping, so the timers behave as in real time.
+#[derive(Debug, Default)]
+pub struct NullClock {
+    elapsed: Duration,
+}
+
+impl Clock for NullClock {
+    fn now(&self) -> Duration {
+        self.elapsed
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.elapsed += duration;
+    }
+}
+
+/// Clock sleeping the current thread.
+#[derive(Debug)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
@@ -0,0 +1,208 @@
+//! Configuration file, holding defaults of the command line options.
+//!
+//! Top-level keys are named after the long options, e.g. `scale = 12` or
+//! `theme = "amber"`. The `[rom.<SHA-1>]` tables hold the settings of the ROM
+//! of the given SHA-1, overriding the top-level ones:
+//!
+//! ```toml
+//! cps = 700
+//!
+//! # INVADERS
+//! [rom.f100197f0f2f05b4f3c8c31ab9c2c3930d3e9571]
+//! cps = 1000
+//! ```
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+use crate::error::{Chip8Error, Result};
+
+/// Options that can be set in the configuration file, by long name.
+pub const SETTINGS: &[&str] = &[
+    "frontend",
+    "scale",
+    "theme",
+    "palette",
+    "fullscreen",
+    "vsync",
+    "keymap",
+    "layout",
+    "release-delay",
+    "quirks",
+    "ipf",
+    "cps",
+    "fps",
+    "frequency",
+    "volume",
+    "waveform",
+    "rewind-frames",
+    "rewind-memory",
+];
+
+/// Settings of the configuration file, as the values of long options.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config {
+    settings: Vec<(String, String)>,
+    /// Settings of each ROM, by hex SHA-1
+    roms: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/chip8/config.toml`, defaulting to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(dir.join("chip8").join("config.toml"))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|message| Chip8Error::InvalidConfig {
+                file: path.display().to_string(),
+                message,
+            })
+    }
+
+    /// Settings for the given ROM: those of its table over the top-level ones.
+    pub fn settings(&self, rom: Option<&[u8]>) -> Vec<(String, String)> {
+        let sha1 = rom.map(sha1);
+        let mut settings = self.settings.clone();
+        let rom_settings = self
+            .roms
+            .iter()
+            .filter(|(hash, _)| Some(hash) == sha1.as_ref());
+        for (key, value) in rom_settings.flat_map(|(_, settings)| settings) {
+            settings.retain(|(set, _)| set != key);
+            settings.push((key.clone(), value.clone()));
+        }
+        settings
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let table: toml::Table = s
+            .parse()
+            .map_err(|err: toml::de::Error| err.message().to_string())?;
+        let mut config = Config::default();
+
+        for (key, value) in table {
+            match value {
+                toml::Value::Table(roms) if key == "rom" => {
+                    for (hash, settings) in roms {
+                        let toml::Value::Table(settings) = settings else {
+                            return Err(format!("`rom.{}` is not a table", hash));
+                        };
+                        if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                            return Err(format!("`rom.{}` is not a SHA-1", hash));
+                        }
+                        config
+                            .roms
+                            .push((hash.to_ascii_lowercase(), parse_settings(settings)?));
+                    }
+                }
+                value => config
+                    .settings
+                    .extend(parse_settings([(key, value)].into_iter().collect())?),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_settings(table: toml::Table) -> std::result::Result<Vec<(String, String)>, String> {
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            if !SETTINGS.contains(&key.as_str()) {
+                return Err(format!("unknown setting `{}`", key));
+            }
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                _ => return Err(format!("`{}` must be a string, a number or a boolean", key)),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Lowercase hex SHA-1 of the ROM.
+pub fn sha1(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            write!(hex, "{:02x}", byte).unwrap();
+            hex
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_settings() {
+        let config: Config = format!(
+            "scale = 8\ncps = 700\nvsync = true\n[rom.{}]\ncps = 1000\nquirks = \"cosmac-vip\"",
+            sha1(b"ROM")
+        )
+        .parse()
+        .unwrap();
+
+        let settings = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            config.settings(None),
+            settings(&[("cps", "700"), ("scale", "8"), ("vsync", "true")])
+        );
+        assert_eq!(
+            config.settings(Some(b"ROM")),
+            settings(&[
+                ("scale", "8"),
+                ("vsync", "true"),
+                ("cps", "1000"),
+                ("quirks", "cosmac-vip")
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert_eq!(
+            "speed = 3".parse::<Config>(),
+            Err("unknown setting `speed`".to_string())
+        );
+        assert_eq!(
+            "[rom.BLITZ]\ncps = 3".parse::<Config>(),
+            Err("`rom.BLITZ` is not a SHA-1".to_string())
+        );
+        assert_eq!(
+            "scale = [1, 2]".parse::<Config>(),
+            Err("`scale` must be a string, a number or a boolean".to_string())
+        );
+        assert!("scale = ".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn sha1_hex() {
+        assert_eq!(sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+}
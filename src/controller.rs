@@ -0,0 +1,142 @@
+//! Game controllers of the SDL2 frontend, their buttons bound to the keypad
+//! by the keymap like keys.
+
+use sdl2::controller::{Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+
+use crate::keyboard::{Key, KeyEvent};
+use crate::keymap::{self, Keymap};
+
+/// Game controllers opened when plugged in and closed when unplugged,
+/// including those plugged in before starting.
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    opened: Vec<GameController>,
+    keymap: Vec<(Button, Key)>,
+}
+
+impl Controllers {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Result<Controllers, String> {
+        let mut controllers = Controllers {
+            subsystem: sdl_context.game_controller()?,
+            opened: vec![],
+            keymap: vec![],
+        };
+        controllers.set_keymap(&Keymap::default());
+        Ok(controllers)
+    }
+
+    pub fn set_keymap(&mut self, keymap: &Keymap) {
+        self.keymap = keymap
+            .bindings()
+            .iter()
+            .filter_map(|(name, key)| {
+                Some((Button::from_string(keymap::button_name(name)?)?, *key))
+            })
+            .collect();
+    }
+
+    fn map(&self, button: Button) -> Option<Key> {
+        self.keymap
+            .iter()
+            .find(|(bound, _)| *bound == button)
+            .map(|(_, key)| *key)
+    }
+
+    /// Handle a game controller event, returning the keypad event it is bound to.
+    pub fn handle(&mut self, event: &Event) -> Option<KeyEvent> {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.subsystem.open(which) {
+                    Ok(controller) => {
+                        log::info!("Game controller connected: {}", controller.name());
+                        let id = controller.instance_id();
+                        self.opened.retain(|opened| opened.instance_id() != id);
+                        self.opened.push(controller);
+                    }
+                    Err(err) => log::warn!("Cannot open game controller {}: {}", which, err),
+                }
+                None
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.opened
+                    .retain(|controller| controller.instance_id() != which);
+                None
+            }
+            Event::ControllerButtonDown { button, .. } => self.map(button).map(KeyEvent::Pressed),
+            Event::ControllerButtonUp { button, .. } => self.map(button).map(KeyEvent::Released),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::sys;
+
+    /// Buttons of the virtual controller, in the order of [`Button`]
+    const MAPPING: &str = "a:b0,b:b1,x:b2,y:b3,back:b4,guide:b5,start:b6,leftstick:b7,\
+        rightstick:b8,leftshoulder:b9,rightshoulder:b10,dpup:b11,dpdown:b12,dpleft:b13,\
+        dpright:b14";
+
+    #[test]
+    fn virtual_controller() {
+        let sdl_context = sdl2::init().unwrap();
+        let mut controllers = Controllers::new(&sdl_context).unwrap();
+        controllers.set_keymap(&"5=PadUp".parse().unwrap());
+        let mut event_pump = sdl_context.event_pump().unwrap();
+        let mut poll = |controllers: &mut Controllers| -> Vec<KeyEvent> {
+            event_pump
+                .poll_iter()
+                .filter_map(|event| controllers.handle(&event))
+                .collect()
+        };
+
+        // Virtual joysticks are identified by `v` and their type in the last
+        // bytes of their GUID, the mapping must be known before plugging it
+        let guid = format!(
+            "{:028x}{:02x}{:02x}",
+            0,
+            b'v',
+            sys::SDL_JoystickType::SDL_JOYSTICK_TYPE_GAMECONTROLLER as u8
+        );
+        controllers
+            .subsystem
+            .add_mapping(&format!("{},Virtual controller,{}", guid, MAPPING))
+            .unwrap();
+        let index = unsafe {
+            sys::SDL_JoystickAttachVirtual(
+                sys::SDL_JoystickType::SDL_JOYSTICK_TYPE_GAMECONTROLLER,
+                0,
+                15,
+                0,
+            )
+        };
+        assert!(index >= 0, "{}", sdl2::get_error());
+        assert_eq!(poll(&mut controllers), vec![]);
+        assert_eq!(controllers.opened.len(), 1);
+
+        let joystick = unsafe { sys::SDL_JoystickOpen(index) };
+        let press = |button: Button, pressed: bool| unsafe {
+            sys::SDL_JoystickSetVirtualButton(joystick, button as i32, pressed as u8);
+        };
+        press(Button::DPadUp, true);
+        press(Button::A, true);
+        assert_eq!(
+            poll(&mut controllers),
+            vec![KeyEvent::Pressed(Key::Num5), KeyEvent::Pressed(Key::Num5)]
+        );
+        press(Button::DPadUp, false);
+        press(Button::Guide, true);
+        assert_eq!(poll(&mut controllers), vec![KeyEvent::Released(Key::Num5)]);
+
+        unsafe {
+            sys::SDL_JoystickClose(joystick);
+            sys::SDL_JoystickDetachVirtual(index);
+        }
+        assert_eq!(poll(&mut controllers), vec![]);
+        assert!(controllers.opened.is_empty());
+    }
+}
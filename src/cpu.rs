@@ -1,10 +1,16 @@
-mod instructions;
+pub(crate) mod instructions;
+mod snapshot;
+#[cfg(test)]
+mod spec;
 
 use instructions::Instruction;
 
 use crate::{
-    constants::{HEIGHT, WIDTH},
-    keyboard::{self, Key},
+    error::{Chip8Error, Result},
+    keyboard::KeyState,
+    quirks::Quirks,
+    rng::Rng,
+    vram::{Vram, PLANES},
 };
 use std::convert::*;
 use std::fmt;
@@ -28,13 +34,38 @@ const FONTS: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
-const RAM_SIZE: usize = 4096;
+/// SUPER-CHIP 8x10 fonts, stored right after `FONTS`
+const HIRES_FONTS: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+const HIRES_FONTS_START: usize = FONTS.len();
+
+/// XO-CHIP extends the original 4K of RAM to 64K
+pub(crate) const RAM_SIZE: usize = 65536;
+pub(crate) const ROM_START: usize = 0x200;
 
 pub struct Cpu {
     ram: [u8; RAM_SIZE],
+    /// End of the RAM in use, the bytes after it being all zero
+    ram_used: usize,
     stack: [u16; 16],
 
-    vram: [[bool; HEIGHT]; WIDTH],
+    vram: Vram,
     vram_changed: bool,
 
     // Registers
@@ -46,21 +77,51 @@ pub struct Cpu {
 
     delay_timer: u8,
     sound_timer: u8,
+
+    /// SUPER-CHIP RPL user flags, saved by FX75 and restored by FX85
+    rpl: [u8; 16],
+    /// Set by 00FD
+    halted: bool,
+    /// Key pressed while waiting in FX0A, stored once released
+    awaited_key: Option<u8>,
+
+    /// XO-CHIP audio pattern buffer, loaded by F002
+    audio_pattern: Option<[u8; 16]>,
+    /// XO-CHIP pitch register, set by FX3A
+    pitch: u8,
+    audio_changed: bool,
+
+    quirks: Quirks,
+    /// A sprite has been drawn since the last 60Hz tick (`display_wait` quirk)
+    drawn_this_frame: bool,
+
+    /// Random source of CXKK
+    rng: Rng,
 }
 
 impl Default for Cpu {
     fn default() -> Cpu {
         Cpu {
             ram: [0; RAM_SIZE],
+            ram_used: 0,
             stack: [0; 16],
-            vram: [[false; HEIGHT]; WIDTH],
+            vram: Vram::default(),
             vram_changed: false,
             v: [0; 16],
             i: 0,
             delay_timer: 0,
             sound_timer: 0,
-            pc: 0x200,
+            pc: ROM_START,
             sp: 0,
+            rpl: [0; 16],
+            halted: false,
+            awaited_key: None,
+            audio_pattern: None,
+            pitch: 64,
+            audio_changed: false,
+            quirks: Quirks::default(),
+            drawn_this_frame: false,
+            rng: Rng::default(),
         }
     }
 }
@@ -71,27 +132,122 @@ impl Cpu {
         for (i, byte) in FONTS.iter().enumerate() {
             self.ram[i] = *byte;
         }
+        self.ram[HIRES_FONTS_START..HIRES_FONTS_START + HIRES_FONTS.len()]
+            .copy_from_slice(&HIRES_FONTS);
     }
 
-    pub fn load_rom(&mut self, bytecode: &[u8]) {
-        self.load_fonts();
-        // TODO check out of bounds
-        for (index, val) in bytecode.iter().enumerate() {
-            self.ram[0x200 + index] = *val;
+    pub fn load_rom(&mut self, bytecode: &[u8]) -> Result<()> {
+        let max = RAM_SIZE - ROM_START;
+        if bytecode.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: bytecode.len(),
+                max,
+            });
         }
+
+        self.load_fonts();
+        self.ram[ROM_START..ROM_START + bytecode.len()].copy_from_slice(bytecode);
+        self.ram_used = self.ram_used.max(ROM_START + bytecode.len());
+
+        Ok(())
     }
 
     pub fn refresh_screen(&self) -> bool {
         self.vram_changed
     }
 
-    pub fn vram(&self) -> &[[bool; HEIGHT]; WIDTH] {
+    pub fn vram(&self) -> &Vram {
         &self.vram
     }
 
+    /// Whether the program exited through 00FD
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn rpl_flags(&self) -> [u8; 16] {
+        self.rpl
+    }
+
+    pub fn set_rpl_flags(&mut self, rpl: [u8; 16]) {
+        self.rpl = rpl;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc as u16
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    /// V0 to VF registers
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp as u8
+    }
+
+    /// Return addresses pushed on the stack, the most recent one last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Opcode of the next instruction to execute, if the program counter is within the RAM
+    pub fn opcode(&self) -> Option<u16> {
+        let bytes = self.ram.get(self.pc..self.pc + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Whether the audio pattern or the pitch changed during the last tick
+    pub fn refresh_audio(&self) -> bool {
+        self.audio_changed
+    }
+
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Seed of the random generator used by CXKK, random unless set.
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    /// Restart the random generator from `seed`, making CXKK reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Must be called at 60Hz
     pub fn decrease_timers(&mut self) {
-        // XXX: This couples frequency's timers with cpu's frequency.
-        // In theory these timers must run at a 60HZ frequency, independently from cpu's freq.
+        self.drawn_this_frame = false;
+
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -100,26 +256,63 @@ impl Cpu {
         }
     }
 
-    pub fn tick(&mut self, pressed_keys: Vec<keyboard::Key>) {
+    pub fn tick(&mut self, keys: &KeyState) -> Result<()> {
         self.vram_changed = false;
-        let opcode: Opcode = self.ram[self.pc as usize..(self.pc + 2) as usize]
+        self.audio_changed = false;
+        let opcode: Opcode = self
+            .ram
+            .get(self.pc..self.pc + 2)
+            .ok_or(Chip8Error::PcOutOfBounds {
+                address: self.pc as u16,
+            })?
             .try_into()
             .unwrap();
         log::debug!("Opcode {}", &opcode);
-        let instruction = Instruction::decode(opcode);
-        self.run_instruction(&instruction, pressed_keys);
+        let instruction = Instruction::decode(&opcode).ok_or(Chip8Error::UnknownOpcode {
+            opcode: opcode.raw(),
+            address: self.pc as u16,
+        })?;
+        self.run_instruction(&instruction, keys)
     }
 
-    fn run_instruction(&mut self, instruction: &Instruction, pressed_keys: Vec<keyboard::Key>) {
+    /// Return the address stored in I if the `len` bytes starting there are within the RAM.
+    fn checked_i(&self, len: usize) -> Result<usize> {
+        let start = self.i as usize;
+        if start + len > RAM_SIZE {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                i: self.i,
+                address: self.pc as u16,
+            });
+        }
+        Ok(start)
+    }
+
+    /// Like [`checked_i`](Cpu::checked_i), for the `len` bytes about to be written there.
+    fn written_i(&mut self, len: usize) -> Result<usize> {
+        let start = self.checked_i(len)?;
+        self.ram_used = self.ram_used.max(start + len);
+        Ok(start)
+    }
+
+    fn run_instruction(&mut self, instruction: &Instruction, keys: &KeyState) -> Result<()> {
         let jump = match *instruction {
-            Instruction::Nop => None,
+            Instruction::Sys { .. } => None,
             Instruction::Cls => self.i_00e0(),
-            Instruction::Rts => self.i_00ee(),
+            Instruction::Rts => self.i_00ee()?,
+            Instruction::Scd { n } => self.i_00cn(&n),
+            Instruction::Scu { n } => self.i_00dn(&n),
+            Instruction::Scr => self.i_00fb(),
+            Instruction::Scl => self.i_00fc(),
+            Instruction::Exit => self.i_00fd(),
+            Instruction::Low => self.i_00fe(),
+            Instruction::High => self.i_00ff(),
             Instruction::Jmp { nnn } => self.i_1nnn(nnn),
-            Instruction::Call { nnn } => self.i_2nnn(nnn),
+            Instruction::Call { nnn } => self.i_2nnn(nnn)?,
             Instruction::Ske { x, kk } => self.i_3xkk(&x, &kk),
             Instruction::Skne { x, kk } => self.i_4xkk(&x, &kk),
             Instruction::Skre { x, y } => self.i_5xy0(&x, &y),
+            Instruction::Srng { x, y } => self.i_5xy2(&x, &y)?,
+            Instruction::Lrng { x, y } => self.i_5xy3(&x, &y)?,
             Instruction::Load { x, kk } => self.i_6xkk(&x, kk),
             Instruction::Add { x, kk } => self.i_7xkk(&x, &kk),
             Instruction::Move { x, y } => self.i_8xy0(&x, &y),
@@ -129,30 +322,45 @@ impl Cpu {
             Instruction::Addr { x, y } => self.i_8xy4(&x, &y),
             Instruction::Sub { x, y } => self.i_8xy5(&x, &y),
             Instruction::Shr { x, y } => self.i_8xy6(&x, &y),
+            Instruction::Subn { x, y } => self.i_8xy7(&x, &y),
             Instruction::Shl { x, y } => self.i_8xye(&x, &y),
             Instruction::Skrne { x, y } => self.i_9xy0(&x, &y),
             Instruction::Loadi { nnn } => self.i_annn(nnn),
+            Instruction::Ldil => self.i_f000()?,
             Instruction::Jumpi { nnn } => self.i_bnnn(&nnn),
             Instruction::Rand { x, kk } => self.i_cxkk(&x, &kk),
-            Instruction::Draw { x, y, n } => self.i_dxyn(&x, &y, &n),
-            Instruction::Skpr { x } => self.i_ex9e(&x, pressed_keys),
-            Instruction::Skup { x } => self.i_exa1(&x, pressed_keys),
+            Instruction::Draw { x, y, n } => self.i_dxyn(&x, &y, &n)?,
+            Instruction::Skpr { x } => self.i_ex9e(&x, keys),
+            Instruction::Skup { x } => self.i_exa1(&x, keys),
             Instruction::Moved { x } => self.i_fx07(&x),
-            Instruction::Keyd { x } => self.i_fx0a(&x, pressed_keys),
+            Instruction::Keyd { x } => self.i_fx0a(&x, keys),
+            Instruction::Plane { n } => self.i_fn01(&n),
+            Instruction::Audio => self.i_f002()?,
             Instruction::Loadd { x } => self.i_fx15(&x),
             Instruction::Loads { x } => self.i_fx18(&x),
             Instruction::Addi { x } => self.i_fx1e(&x),
             Instruction::Ldspr { x } => self.i_fx29(&x),
-            Instruction::Bcd { x } => self.i_fx33(&x),
-            Instruction::Stor { x } => self.i_fx55(&x),
-            Instruction::Read { x } => self.i_fx65(&x),
+            Instruction::Ldhf { x } => self.i_fx30(&x),
+            Instruction::Pitch { x } => self.i_fx3a(&x),
+            Instruction::Bcd { x } => self.i_fx33(&x)?,
+            Instruction::Stor { x } => self.i_fx55(&x)?,
+            Instruction::Read { x } => self.i_fx65(&x)?,
+            Instruction::Srpl { x } => self.i_fx75(&x),
+            Instruction::Lrpl { x } => self.i_fx85(&x),
         };
 
         match jump.unwrap_or(PC::Advance(1)) {
             PC::Wait => (),
             PC::Advance(i) => self.pc += 2_usize * i as usize,
+            PC::Skip => {
+                // XO-CHIP: F000 NNNN is twice as long as any other instruction
+                let long = self.ram.get(self.pc + 2..self.pc + 4) == Some(&[0xF0, 0x00]);
+                self.pc += if long { 6 } else { 4 };
+            }
             PC::Jump(nnn) => self.pc = nnn as usize,
         };
+
+        Ok(())
     }
 }
 
@@ -161,9 +369,64 @@ impl Cpu {
     /// 0x00E0 - CLS
     /// Clear the display.
     fn i_00e0(&mut self) -> Option<PC> {
-        self.vram = [[false; HEIGHT]; WIDTH];
+        self.vram.clear();
+        self.vram_changed = true;
+
+        None
+    }
+
+    /// 00CN - SCD nibble (SUPER-CHIP)
+    /// Scroll the display down N lines.
+    fn i_00cn(&mut self, n: &u8) -> Option<PC> {
+        self.vram.scroll_down(*n as usize);
+        self.vram_changed = true;
+        None
+    }
+
+    /// 00DN - SCU nibble (XO-CHIP)
+    /// Scroll the display up N lines.
+    fn i_00dn(&mut self, n: &u8) -> Option<PC> {
+        self.vram.scroll_up(*n as usize);
+        self.vram_changed = true;
+        None
+    }
+
+    /// 00FB - SCR (SUPER-CHIP)
+    /// Scroll the display right 4 pixels.
+    fn i_00fb(&mut self) -> Option<PC> {
+        self.vram.scroll_right(4);
         self.vram_changed = true;
+        None
+    }
 
+    /// 00FC - SCL (SUPER-CHIP)
+    /// Scroll the display left 4 pixels.
+    fn i_00fc(&mut self) -> Option<PC> {
+        self.vram.scroll_left(4);
+        self.vram_changed = true;
+        None
+    }
+
+    /// 00FD - EXIT (SUPER-CHIP)
+    /// Exit the interpreter.
+    fn i_00fd(&mut self) -> Option<PC> {
+        self.halted = true;
+        Some(PC::Wait)
+    }
+
+    /// 00FE - LOW (SUPER-CHIP)
+    /// Disable high resolution mode (64x32).
+    fn i_00fe(&mut self) -> Option<PC> {
+        self.vram.set_hires(false);
+        self.vram_changed = true;
+        None
+    }
+
+    /// 00FF - HIGH (SUPER-CHIP)
+    /// Enable high resolution mode (128x64).
+    fn i_00ff(&mut self) -> Option<PC> {
+        self.vram.set_hires(true);
+        self.vram_changed = true;
         None
     }
 
@@ -172,11 +435,16 @@ impl Cpu {
     ///
     /// The interpreter sets the program counter to the address at the top of the stack,
     /// then subtracts 1 from the stack pointer.
-    fn i_00ee(&mut self) -> Option<PC> {
-        self.pc = self.stack[self.sp as usize - 1] as usize;
+    fn i_00ee(&mut self) -> Result<Option<PC>> {
+        if self.sp == 0 {
+            return Err(Chip8Error::StackUnderflow {
+                address: self.pc as u16,
+            });
+        }
+        self.pc = self.stack[self.sp - 1] as usize;
         self.sp -= 1;
 
-        None
+        Ok(None)
     }
 
     /// 1nnn - JP addr
@@ -190,13 +458,19 @@ impl Cpu {
     /// 2NNN
     ///
     /// Execute subroutine starting at address NNN
-    fn i_2nnn(&mut self, nnn: u16) -> Option<PC> {
+    fn i_2nnn(&mut self, nnn: u16) -> Result<Option<PC>> {
+        if self.sp == self.stack.len() {
+            return Err(Chip8Error::StackOverflow {
+                address: self.pc as u16,
+            });
+        }
+
         // push PC to the stack to return later
-        self.stack[self.sp as usize] = self.pc.try_into().expect("pc must always fit within a u16");
+        self.stack[self.sp] = self.pc.try_into().expect("pc must always fit within a u16");
         self.sp += 1;
 
         // call the subroutine
-        Some(PC::Jump(nnn))
+        Ok(Some(PC::Jump(nnn)))
     }
 
     /// 3xkk - SE Vx, byte
@@ -206,7 +480,7 @@ impl Cpu {
     /// the program counter by 2.
     fn i_3xkk(&mut self, x: &u8, kk: &u8) -> Option<PC> {
         if self.v[*x as usize] == *kk {
-            return Some(PC::Advance(2));
+            return Some(PC::Skip);
         }
         None
     }
@@ -216,7 +490,7 @@ impl Cpu {
         if self.v[*x as usize] == *kk {
             return None;
         }
-        Some(PC::Advance(2))
+        Some(PC::Skip)
     }
 
     /// Skip the following instruction if the value of register VX is equal to the
@@ -225,7 +499,31 @@ impl Cpu {
         if self.v[*x as usize] != self.v[*y as usize] {
             return None;
         }
-        Some(PC::Advance(2))
+        Some(PC::Skip)
+    }
+
+    /// 5xy2 - SAVE Vx - Vy (XO-CHIP)
+    /// Store registers VX to VY inclusive in memory starting at address I, without
+    /// modifying I. Registers are stored in reverse order if X > Y.
+    fn i_5xy2(&mut self, x: &u8, y: &u8) -> Result<Option<PC>> {
+        let registers = register_range(*x, *y);
+        let start = self.written_i(registers.len())?;
+        for (offset, reg) in registers.into_iter().enumerate() {
+            self.ram[start + offset] = self.v[reg];
+        }
+        Ok(None)
+    }
+
+    /// 5xy3 - LOAD Vx - Vy (XO-CHIP)
+    /// Fill registers VX to VY inclusive with the values stored in memory starting at
+    /// address I, without modifying I. Registers are loaded in reverse order if X > Y.
+    fn i_5xy3(&mut self, x: &u8, y: &u8) -> Result<Option<PC>> {
+        let registers = register_range(*x, *y);
+        let start = self.checked_i(registers.len())?;
+        for (offset, reg) in registers.into_iter().enumerate() {
+            self.v[reg] = self.ram[start + offset];
+        }
+        Ok(None)
     }
 
     /// 6xkk - LD Vx, byte
@@ -252,76 +550,85 @@ impl Cpu {
     }
 
     /// Set VX to VX OR VY
+    /// VF is reset to 0 with the `vf_reset` quirk
     fn i_8xy1(&mut self, x: &u8, y: &u8) -> Option<PC> {
         self.v[*x as usize] |= self.v[*y as usize];
+        self.vf_reset();
         None
     }
 
     /// Set VX to VX AND VY
+    /// VF is reset to 0 with the `vf_reset` quirk
     fn i_8xy2(&mut self, x: &u8, y: &u8) -> Option<PC> {
         self.v[*x as usize] &= self.v[*y as usize];
+        self.vf_reset();
         None
     }
 
     /// Set VX to VX XOR VY
+    /// VF is reset to 0 with the `vf_reset` quirk
     fn i_8xy3(&mut self, x: &u8, y: &u8) -> Option<PC> {
         self.v[*x as usize] ^= self.v[*y as usize];
+        self.vf_reset();
         None
     }
 
+    fn vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+    }
+
     /// ADD VX, VY
     ///
     /// Set VX equal to VX plus VY. In the case of an overflow VF is set to 1.
     /// Otherwise 0.
+    /// The flag is written last, so it overrides the result when X is F.
     fn i_8xy4(&mut self, x: &u8, y: &u8) -> Option<PC> {
-        // XXX: This can be optimized by using `overflowing_add`.
-
-        match self.v[*x as usize].checked_add(self.v[*y as usize]) {
-            None => self.v[0xF] = 1,
-            Some(_) => self.v[0xF] = 0,
-        }
-
-        self.v[*x as usize] = self.v[*x as usize].wrapping_add(self.v[*y as usize]);
-
-        None
+        let (result, carry) = self.v[*x as usize].overflowing_add(self.v[*y as usize]);
+        self.set_with_flag(x, result, carry)
     }
 
     /// SUB VX, VY
     ///
     /// Set VX equal to VX minus VY. In the case of an underflow VF is set 0.
-    /// Otherwise 1. (VF = VX > VY)
+    /// Otherwise 1. (VF = VX >= VY)
     fn i_8xy5(&mut self, x: &u8, y: &u8) -> Option<PC> {
-        self.v[0xF] = (self.v[*x as usize] > self.v[*y as usize]) as u8;
-
-        self.v[*x as usize] = self.v[*x as usize].wrapping_sub(self.v[*y as usize]);
-
-        None
+        let (result, borrow) = self.v[*x as usize].overflowing_sub(self.v[*y as usize]);
+        self.set_with_flag(x, result, !borrow)
     }
 
     /// Store the value of register VY shifted right one bit in register VX
     /// Set register VF to the least significant bit prior to the shift
+    /// VX is shifted in place with the `shift` quirk
     fn i_8xy6(&mut self, x: &u8, y: &u8) -> Option<PC> {
-        self.v[0xF_usize] = if (self.v[*y as usize] & 0x01) == 0 {
-            0_u8
-        } else {
-            1_u8
-        };
-
-        self.v[*x as usize] = self.v[*y as usize] >> 1;
+        let y = if self.quirks.shift { x } else { y };
+        let value = self.v[*y as usize];
+        self.set_with_flag(x, value >> 1, value & 0x01 != 0)
+    }
 
-        None
+    /// SUBN VX, VY
+    ///
+    /// Set VX equal to VY minus VX. In the case of an underflow VF is set 0.
+    /// Otherwise 1. (VF = VY >= VX)
+    fn i_8xy7(&mut self, x: &u8, y: &u8) -> Option<PC> {
+        let (result, borrow) = self.v[*y as usize].overflowing_sub(self.v[*x as usize]);
+        self.set_with_flag(x, result, !borrow)
     }
 
     /// Store the value of register VY shifted left one bit in register VX
     /// Set register VF to the most significant bit prior to the shift
+    /// VX is shifted in place with the `shift` quirk
     fn i_8xye(&mut self, x: &u8, y: &u8) -> Option<PC> {
-        self.v[0xF_usize] = if (self.v[*y as usize] & 0x80) == 0 {
-            0_u8
-        } else {
-            1_u8
-        };
-        self.v[*x as usize] = self.v[*y as usize] << 1;
+        let y = if self.quirks.shift { x } else { y };
+        let value = self.v[*y as usize];
+        self.set_with_flag(x, value << 1, value & 0x80 != 0)
+    }
 
+    /// Store the result of an arithmetic instruction in VX, then its flag in VF.
+    fn set_with_flag(&mut self, x: &u8, result: u8, flag: bool) -> Option<PC> {
+        self.v[*x as usize] = result;
+        self.v[0xF] = flag as u8;
         None
     }
 
@@ -331,7 +638,7 @@ impl Cpu {
         if self.v[*x as usize] == self.v[*y as usize] {
             return None;
         }
-        Some(PC::Advance(2))
+        Some(PC::Skip)
     }
 
     /// Annn - LD I, addr
@@ -343,14 +650,29 @@ impl Cpu {
         None
     }
 
+    /// F000 NNNN - LD I, long addr (XO-CHIP)
+    /// Set I = NNNN, the 16-bit value following the instruction.
+    fn i_f000(&mut self) -> Result<Option<PC>> {
+        let nnnn = self
+            .ram
+            .get(self.pc + 2..self.pc + 4)
+            .ok_or(Chip8Error::PcOutOfBounds {
+                address: self.pc as u16,
+            })?;
+        self.i = u16::from_be_bytes([nnnn[0], nnnn[1]]);
+        Ok(Some(PC::Advance(2)))
+    }
+
     /// Jump to address NNN + V0
+    /// With the `jump` quirk, this is BXNN: jump to address XNN + VX
     fn i_bnnn(&mut self, nnn: &u16) -> Option<PC> {
-        Some(PC::Jump(*nnn + self.v[0] as u16))
+        let x = if self.quirks.jump { *nnn >> 8 } else { 0 };
+        Some(PC::Jump(*nnn + self.v[x as usize] as u16))
     }
 
     /// Set VX to a random number with a mask of kk
     fn i_cxkk(&mut self, x: &u8, kk: &u8) -> Option<PC> {
-        self.v[*x as usize] = utils::random_byte() & *kk;
+        self.v[*x as usize] = self.rng.next_byte() & *kk;
         None
     }
 
@@ -362,42 +684,67 @@ impl Cpu {
     /// Sprites are XORed onto the existing screen.
     /// If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
     /// If the sprite is positioned so part of it is outside the coordinates of the
-    /// display, it wraps around to the opposite side of the screen.
+    /// display, it wraps around to the opposite side of the screen, or it is clipped
+    /// with the `clipping` quirk.
     /// See instruction 8xy3 for more information on XOR, and section 2.4, Display
     /// for more information on the Chip-8 screen and sprites.
-    fn i_dxyn(&mut self, x: &u8, y: &u8, n: &u8) -> Option<PC> {
+    ///
+    /// DXY0 (SUPER-CHIP) draws a 16x16 sprite made of 32 bytes, two per row.
+    ///
+    /// With several XO-CHIP planes selected, the sprite of each plane follows the
+    /// previous one in memory.
+    fn i_dxyn(&mut self, x: &u8, y: &u8, n: &u8) -> Result<Option<PC>> {
+        if self.quirks.display_wait && self.drawn_this_frame {
+            return Ok(Some(PC::Wait));
+        }
+        let (rows, row_bytes) = if *n == 0 { (16, 2) } else { (*n as usize, 1) };
+        let planes = self.vram.planes();
+        let sprite_len = rows * row_bytes;
+        let mut start = self.checked_i(sprite_len * planes.count_ones() as usize)?;
         self.vram_changed = true;
+        self.drawn_this_frame = true;
 
-        let vx = ((self.v[*x as usize] as usize) % WIDTH) as usize;
-        let vy = ((self.v[*y as usize] as usize) % HEIGHT) as usize;
+        let width = self.vram.width();
+        let height = self.vram.height();
+        let vx = (self.v[*x as usize] as usize) % width;
+        let vy = (self.v[*y as usize] as usize) % height;
 
         self.v[0xF] = 0;
-        for jj in 0..*n {
-            let yy = (vy + jj as usize) % HEIGHT;
-            let byte_ii = self.ram[(self.i + (jj as u16)) as usize];
-            for ii in 0..8 {
-                let xx = (vx + ii as usize) % WIDTH;
-                let pixel_new = ((byte_ii >> (7 - ii)) & 0x01) != 0;
-                let pixel = self.vram[xx][yy];
-
-                self.v[0xF] |= (pixel_new & pixel) as u8;
-                self.vram[xx][yy] ^= pixel_new;
+        for plane in (0..PLANES).map(|p| 1 << p).filter(|p| planes & p != 0) {
+            for jj in 0..rows {
+                if self.quirks.clipping && vy + jj >= height {
+                    break;
+                }
+                let yy = (vy + jj) % height;
+                let row = &self.ram[start + jj * row_bytes..start + (jj + 1) * row_bytes];
+                let row = row
+                    .iter()
+                    .fold(0_u16, |row, byte| (row << 8) | *byte as u16);
+                for ii in 0..row_bytes * 8 {
+                    if self.quirks.clipping && vx + ii >= width {
+                        break;
+                    }
+                    let xx = (vx + ii) % width;
+                    let pixel_new = ((row >> (row_bytes * 8 - 1 - ii)) & 0x01) != 0;
+
+                    if pixel_new {
+                        self.v[0xF] |= self.vram.toggle(xx, yy, plane) as u8;
+                    }
+                }
             }
+            start += sprite_len;
         }
 
-        None
+        Ok(None)
     }
 
     /// Skip the following instruction if the key corresponding to the hex value currently stored
     /// in register VX is pressed
-    fn i_ex9e(&mut self, x: &u8, pressed_keys: Vec<keyboard::Key>) -> Option<PC> {
+    fn i_ex9e(&mut self, x: &u8, keys: &KeyState) -> Option<PC> {
         let expected_key = self.v[*x as usize];
 
-        if pressed_keys
-            .into_iter()
-            .any(|k| u8::from(k) == expected_key)
-        {
-            Some(PC::Advance(2))
+        if keys.is_pressed(expected_key) {
+            Some(PC::Skip)
         } else {
             Some(PC::Advance(1))
         }
@@ -405,14 +752,11 @@ impl Cpu {
 
     /// Skip the following instruction if the key corresponding to the hex value currently stored
     /// in register VX is not pressed
-    fn i_exa1(&mut self, x: &u8, pressed_keys: Vec<keyboard::Key>) -> Option<PC> {
+    fn i_exa1(&mut self, x: &u8, keys: &KeyState) -> Option<PC> {
         let expected_key = self.v[*x as usize];
 
-        if pressed_keys
-            .into_iter()
-            .all(|k| u8::from(k) != expected_key)
-        {
-            Some(PC::Advance(2))
+        if !keys.is_pressed(expected_key) {
+            Some(PC::Skip)
         } else {
             Some(PC::Advance(1))
         }
@@ -429,18 +773,40 @@ impl Cpu {
     ///
     /// All execution stops until a key is pressed, then the value of that key
     /// is stored in Vx.
-    fn i_fx0a(&mut self, x: &u8, pressed_keys: Vec<keyboard::Key>) -> Option<PC> {
-        let key = pressed_keys.into_iter().find(|k| !matches!(k, Key::Exit));
-
-        match key {
-            None | Some(Key::Exit) => Some(PC::Wait),
-            Some(k) => {
-                self.v[*x as usize] = k.into();
+    /// As in the COSMAC VIP, the key is only stored once it has been released.
+    fn i_fx0a(&mut self, x: &u8, keys: &KeyState) -> Option<PC> {
+        match self.awaited_key {
+            Some(key) if !keys.is_pressed(key) => {
+                self.awaited_key = None;
+                self.v[*x as usize] = key;
                 None
             }
+            Some(_) => Some(PC::Wait),
+            None => {
+                self.awaited_key = keys.first_pressed();
+                Some(PC::Wait)
+            }
         }
     }
 
+    /// Fn01 - PLANE n (XO-CHIP)
+    /// Select the bitplanes affected by drawing, clearing and scrolling.
+    fn i_fn01(&mut self, n: &u8) -> Option<PC> {
+        self.vram.select_planes(*n);
+        None
+    }
+
+    /// F002 - AUDIO (XO-CHIP)
+    /// Load the 16 bytes starting at address I into the audio pattern buffer.
+    fn i_f002(&mut self) -> Result<Option<PC>> {
+        let start = self.checked_i(16)?;
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(&self.ram[start..start + 16]);
+        self.audio_pattern = Some(pattern);
+        self.audio_changed = true;
+        Ok(None)
+    }
+
     /// Set the delay timer to the value of register VX
     fn i_fx15(&mut self, x: &u8) -> Option<PC> {
         self.delay_timer = self.v[*x as usize];
@@ -467,7 +833,15 @@ impl Cpu {
     /// See section 2.4, Display, for more information on the Chip-8
     /// hexadecimal font.
     fn i_fx29(&mut self, x: &u8) -> Option<PC> {
-        self.i = (self.v[*x as usize] * 5) as u16; // 5 is the len of a digit
+        self.i = (self.v[*x as usize] & 0x0F) as u16 * 5; // 5 is the len of a digit
+        None
+    }
+
+    /// Fx30 - LD HF, Vx (SUPER-CHIP)
+    /// Set I = location of the 8x10 sprite for digit Vx.
+    fn i_fx30(&mut self, x: &u8) -> Option<PC> {
+        let digit = (self.v[*x as usize] & 0x0F) as usize;
+        self.i = (HIRES_FONTS_START + digit * 10) as u16; // 10 is the len of a digit
         None
     }
 
@@ -475,43 +849,74 @@ impl Cpu {
     ///
     /// Store the binary-coded decimal equivalent of the value stored in register VX at
     /// addresses I, I+1, and I+2.
-    fn i_fx33(&mut self, x: &u8) -> Option<PC> {
+    fn i_fx33(&mut self, x: &u8) -> Result<Option<PC>> {
+        let start = self.written_i(3)?;
         let mut byte = self.v[*x as usize];
 
         // first figure
-        self.ram[self.i as usize + 2] = byte.rem_euclid(10);
+        self.ram[start + 2] = byte.rem_euclid(10);
 
         // second figure
         byte /= 10;
-        self.ram[self.i as usize + 1] = byte.rem_euclid(10);
+        self.ram[start + 1] = byte.rem_euclid(10);
 
         // third figure
         byte /= 10;
-        self.ram[self.i as usize] = byte.rem_euclid(10);
+        self.ram[start] = byte.rem_euclid(10);
 
-        None
+        Ok(None)
     }
 
     /// Store the values of registers V0 to VX inclusive in memory starting at address I
-    /// I is set to I + X + 1 after operation
-    fn i_fx55(&mut self, x: &u8) -> Option<PC> {
+    /// I is set to I + X + 1 after operation with the `load_store` quirk
+    fn i_fx55(&mut self, x: &u8) -> Result<Option<PC>> {
+        let start = self.written_i(*x as usize + 1)?;
         for i in 0..=*x {
-            self.ram[(self.i as usize) + (i as usize)] = self.v[i as usize];
+            self.ram[start + (i as usize)] = self.v[i as usize];
+        }
+        if self.quirks.load_store {
+            self.i = self.i.wrapping_add(*x as u16 + 1);
         }
-        self.i += *x as u16 + 1;
 
-        None
+        Ok(None)
     }
 
     /// Fill registers V0 to VX inclusive with the values stored in memory starting at address I
-    /// I is set to I + X + 1 after operation
-    fn i_fx65(&mut self, x: &u8) -> Option<PC> {
+    /// I is set to I + X + 1 after operation with the `load_store` quirk
+    fn i_fx65(&mut self, x: &u8) -> Result<Option<PC>> {
+        let start = self.checked_i(*x as usize + 1)?;
         for i in 0..=*x {
-            self.v[i as usize] = self.ram[(self.i as usize) + (i as usize)];
+            self.v[i as usize] = self.ram[start + (i as usize)];
+        }
+
+        if self.quirks.load_store {
+            self.i = self.i.wrapping_add(*x as u16 + 1);
         }
 
-        self.i += *x as u16 + 1;
+        Ok(None)
+    }
+
+    /// Fx3A - PITCH Vx (XO-CHIP)
+    /// Set the pitch register to the value of register VX.
+    fn i_fx3a(&mut self, x: &u8) -> Option<PC> {
+        self.pitch = self.v[*x as usize];
+        self.audio_changed = true;
+        None
+    }
+
+    /// Fx75 - LD R, Vx (SUPER-CHIP)
+    /// Store V0 to VX inclusive in the RPL user flags.
+    fn i_fx75(&mut self, x: &u8) -> Option<PC> {
+        let len = *x as usize + 1;
+        self.rpl[..len].copy_from_slice(&self.v[..len]);
+        None
+    }
 
+    /// Fx85 - LD Vx, R (SUPER-CHIP)
+    /// Read V0 to VX inclusive from the RPL user flags.
+    fn i_fx85(&mut self, x: &u8) -> Option<PC> {
+        let len = *x as usize + 1;
+        self.v[..len].copy_from_slice(&self.rpl[..len]);
         None
     }
 }
@@ -520,6 +925,10 @@ impl Cpu {
 pub struct Opcode([u8; 2]);
 
 impl Opcode {
+    pub fn raw(&self) -> u16 {
+        u16::from_be_bytes(self.0)
+    }
+
     pub fn nibbles(&self) -> (u8, u8, u8, u8) {
         (
             self.0[0] >> 4,
@@ -550,7 +959,7 @@ impl Opcode {
 impl TryFrom<&[u8]> for Opcode {
     type Error = &'static str;
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+    fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
         let raw_opcode: [u8; 2] = value.try_into().unwrap();
         Ok(Opcode(raw_opcode))
     }
@@ -559,7 +968,7 @@ impl TryFrom<&[u8]> for Opcode {
 impl TryFrom<&[u16]> for Opcode {
     type Error = &'static str;
 
-    fn try_from(value: &[u16]) -> Result<Self, Self::Error> {
+    fn try_from(value: &[u16]) -> std::result::Result<Self, Self::Error> {
         let x = value[0].to_be_bytes();
         Ok(Opcode(x))
     }
@@ -574,18 +983,24 @@ impl fmt::Display for Opcode {
 enum PC {
     Wait,
     Advance(u16), // number of positions
+    Skip,         // skip the following instruction
     Jump(u16),
 }
 
-mod utils {
-    pub fn random_byte() -> u8 {
-        rand::random()
+/// Register indexes from X to Y inclusive, descending if X > Y
+fn register_range(x: u8, y: u8) -> Vec<usize> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        (x..=y).collect()
+    } else {
+        (y..=x).rev().collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::{HIRES_WIDTH, WIDTH};
 
     fn create_cpu() -> Cpu {
         let mut cpu = Cpu::default();
@@ -595,17 +1010,17 @@ mod tests {
         cpu
     }
 
-    const NO_KEYS: Vec<Key> = vec![];
+    const NO_KEYS: &KeyState = &KeyState::new();
 
     #[test]
     fn clear_screen_00e0() {
         let mut cpu = create_cpu();
-        cpu.vram[2][4] |= true;
+        cpu.vram.set(2, 4, true);
 
         cpu.i_00e0();
 
-        for pixel in cpu.vram.iter().flatten() {
-            assert_eq!(*pixel, false, "All pixels should have been cleared");
+        for pixel in cpu.vram.pixels() {
+            assert!(!pixel, "All pixels should have been cleared");
             assert!(cpu.vram_changed, "Screen must be updated");
         }
     }
@@ -618,9 +1033,9 @@ mod tests {
         // fifth register is not 0x2A
         cpu.v[5] = 2;
         let rom: &[u8] = &[0x45, 0x2A];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        cpu.tick(NO_KEYS);
+        cpu.tick(NO_KEYS).unwrap();
 
         assert_eq!(cpu.pc, 0x204);
     }
@@ -632,9 +1047,9 @@ mod tests {
 
         cpu.v[5] = 0x2A;
         let rom: &[u8] = &[0x45, 0x2A];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        cpu.tick(NO_KEYS);
+        cpu.tick(NO_KEYS).unwrap();
 
         assert_eq!(cpu.pc, 0x202);
     }
@@ -647,9 +1062,9 @@ mod tests {
         cpu.v[5] = 2;
         cpu.v[4] = 2;
         let rom: &[u8] = &[0x54, 0x50];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        cpu.tick(NO_KEYS);
+        cpu.tick(NO_KEYS).unwrap();
 
         assert_eq!(cpu.pc, 0x204);
     }
@@ -662,9 +1077,9 @@ mod tests {
         cpu.v[5] = 2;
         cpu.v[4] = 5;
         let rom: &[u8] = &[0x54, 0x50];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        cpu.tick(NO_KEYS);
+        cpu.tick(NO_KEYS).unwrap();
 
         assert_eq!(cpu.pc, 0x202);
     }
@@ -676,8 +1091,8 @@ mod tests {
 
         cpu.v[5] = 2;
         let rom: &[u8] = &[0x84, 0x50];
-        cpu.load_rom(rom);
-        cpu.tick(NO_KEYS);
+        cpu.load_rom(rom).unwrap();
+        cpu.tick(NO_KEYS).unwrap();
         assert_eq!(cpu.v[4], 2);
         assert_eq!(cpu.v[5], 2);
         assert_eq!(cpu.pc, 0x202);
@@ -691,9 +1106,9 @@ mod tests {
         cpu.v[4] = 0b1001;
         cpu.v[5] = 0b1010;
         let rom: &[u8] = &[0x84, 0x51];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        cpu.tick(NO_KEYS);
+        cpu.tick(NO_KEYS).unwrap();
 
         assert_eq!(cpu.v[4], 0b1011);
         assert_eq!(cpu.v[5], 0b1010);
@@ -707,9 +1122,9 @@ mod tests {
         cpu.v[4] = 0b1001;
         cpu.v[5] = 0b1010;
         let rom: &[u8] = &[0x84, 0x52];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        cpu.tick(NO_KEYS);
+        cpu.tick(NO_KEYS).unwrap();
 
         assert_eq!(cpu.v[4], 0b1000);
         assert_eq!(cpu.v[5], 0b1010);
@@ -723,9 +1138,9 @@ mod tests {
         cpu.v[4] = 0b1001;
         cpu.v[5] = 0b1010;
         let rom: &[u8] = &[0x84, 0x53];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        cpu.tick(NO_KEYS);
+        cpu.tick(NO_KEYS).unwrap();
 
         assert_eq!(cpu.v[4], 0b0011);
         assert_eq!(cpu.v[5], 0b1010);
@@ -740,9 +1155,9 @@ mod tests {
         cpu.v[5] = 2;
         cpu.v[4] = 5;
         let rom: &[u8] = &[0x94, 0x50];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        cpu.tick(NO_KEYS);
+        cpu.tick(NO_KEYS).unwrap();
 
         assert_eq!(cpu.pc, 0x204);
     }
@@ -755,9 +1170,9 @@ mod tests {
         cpu.v[5] = 2;
         cpu.v[4] = 2;
         let rom: &[u8] = &[0x94, 0x50];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        cpu.tick(NO_KEYS);
+        cpu.tick(NO_KEYS).unwrap();
 
         assert_eq!(cpu.pc, 0x202);
     }
@@ -768,10 +1183,10 @@ mod tests {
 
         cpu.v[1] = 2;
         let rom: &[u8] = &[0xe1, 0x9e];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
 
-        let keys = vec![Key::Num0, Key::Num2];
-        cpu.tick(keys);
+        let keys = KeyState::from(0b0101);
+        cpu.tick(&keys).unwrap();
 
         assert_eq!(cpu.pc, 0x204);
     }
@@ -782,11 +1197,370 @@ mod tests {
 
         cpu.v[1] = 3;
         let rom: &[u8] = &[0xe1, 0x9e];
-        cpu.load_rom(rom);
+        cpu.load_rom(rom).unwrap();
+
+        let keys = KeyState::from(0b0101);
+        cpu.tick(&keys).unwrap();
+
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn load_rom_too_large() {
+        let mut cpu = create_cpu();
+        let rom = vec![0; RAM_SIZE - ROM_START + 1];
+
+        let err = cpu.load_rom(&rom).unwrap_err();
+
+        assert!(matches!(
+            err,
+            Chip8Error::RomTooLarge {
+                size: 65025,
+                max: 65024
+            }
+        ));
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let mut cpu = create_cpu();
+        cpu.load_rom(&[0x80, 0x0F]).unwrap();
+
+        let err = cpu.tick(NO_KEYS).unwrap_err();
+
+        assert!(matches!(
+            err,
+            Chip8Error::UnknownOpcode {
+                opcode: 0x800F,
+                address: 0x200
+            }
+        ));
+    }
+
+    #[test]
+    fn test_00ee_stack_underflow() {
+        let mut cpu = create_cpu();
+        cpu.load_rom(&[0x00, 0xEE]).unwrap();
+
+        let err = cpu.tick(NO_KEYS).unwrap_err();
+
+        assert!(matches!(err, Chip8Error::StackUnderflow { address: 0x200 }));
+    }
+
+    #[test]
+    fn test_2nnn_stack_overflow() {
+        let mut cpu = create_cpu();
+        // CALL 0x200, recursing forever
+        cpu.load_rom(&[0x22, 0x00]).unwrap();
+
+        for _ in 0..16 {
+            cpu.tick(NO_KEYS).unwrap();
+        }
+        let err = cpu.tick(NO_KEYS).unwrap_err();
+
+        assert!(matches!(err, Chip8Error::StackOverflow { address: 0x200 }));
+    }
+
+    #[test]
+    fn test_fx55_out_of_bounds() {
+        let mut cpu = create_cpu();
+        cpu.i = 0xFFFE;
+        cpu.load_rom(&[0xF2, 0x55]).unwrap();
+
+        let err = cpu.tick(NO_KEYS).unwrap_err();
+
+        assert!(matches!(
+            err,
+            Chip8Error::MemoryOutOfBounds {
+                i: 0xFFFE,
+                address: 0x200
+            }
+        ));
+    }
+
+    #[test]
+    fn test_8xy1_vf_reset_quirk() {
+        let mut cpu = create_cpu();
+        cpu.set_quirks(Quirks::COSMAC_VIP);
+        cpu.v[0xF] = 1;
+        cpu.load_rom(&[0x84, 0x51]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn test_8xy6_shift_quirk() {
+        let mut cpu = create_cpu();
+        cpu.set_quirks(Quirks::SUPER_CHIP);
+        cpu.v[4] = 0b1000;
+        cpu.v[5] = 0b0011;
+        cpu.load_rom(&[0x84, 0x56]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.v[4], 0b0100);
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn test_cxkk_seeded() {
+        let rom = [0xC0, 0xFF, 0xC1, 0x0F];
+        let mut cpu = create_cpu();
+        cpu.set_seed(1234);
+        cpu.load_rom(&rom).unwrap();
+        let mut other = create_cpu();
+        other.set_seed(1234);
+        other.load_rom(&rom).unwrap();
+
+        for _ in 0..2 {
+            cpu.tick(NO_KEYS).unwrap();
+            other.tick(NO_KEYS).unwrap();
+        }
+
+        assert_eq!(cpu.v[..2], other.v[..2]);
+        assert_eq!(cpu.v[1] & 0xF0, 0);
+        assert_eq!(cpu.seed(), 1234);
+    }
+
+    #[test]
+    fn test_bnnn_jump_quirk() {
+        let mut cpu = create_cpu();
+        cpu.set_quirks(Quirks::SUPER_CHIP);
+        cpu.v[0] = 0x10;
+        cpu.v[3] = 0x02;
+        cpu.load_rom(&[0xB3, 0x00]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.pc, 0x302);
+    }
+
+    #[test]
+    fn test_fx55_load_store_quirk() {
+        let mut cpu = create_cpu();
+        cpu.i = 0x300;
+        cpu.load_rom(&[0xF2, 0x55, 0xF2, 0x55]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+        assert_eq!(cpu.i, 0x303);
+
+        cpu.set_quirks(Quirks::SUPER_CHIP);
+        cpu.tick(NO_KEYS).unwrap();
+        assert_eq!(cpu.i, 0x303);
+    }
+
+    #[test]
+    fn test_dxyn_clipping_quirk() {
+        let mut cpu = create_cpu();
+        cpu.v[0] = (WIDTH - 2) as u8;
+        cpu.v[1] = 0;
+        // "0" glyph
+        cpu.i = 0;
+        cpu.load_rom(&[0xD0, 0x11, 0x00, 0xE0, 0xD0, 0x11]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+        assert!(cpu.vram.get(0, 0), "Sprite must wrap around");
+
+        cpu.set_quirks(Quirks::COSMAC_VIP);
+        cpu.tick(NO_KEYS).unwrap();
+        cpu.decrease_timers();
+        cpu.tick(NO_KEYS).unwrap();
+        assert!(cpu.vram.get(WIDTH - 1, 0));
+        assert!(!cpu.vram.get(0, 0), "Sprite must be clipped");
+    }
+
+    #[test]
+    fn test_dxyn_display_wait_quirk() {
+        let mut cpu = create_cpu();
+        cpu.set_quirks(Quirks::COSMAC_VIP);
+        cpu.load_rom(&[0xD0, 0x11, 0xD0, 0x11]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+        cpu.tick(NO_KEYS).unwrap();
+        assert_eq!(cpu.pc, 0x202, "Second draw must wait for the next frame");
+
+        cpu.decrease_timers();
+        cpu.tick(NO_KEYS).unwrap();
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn test_00ff_hires_dxy0() {
+        let mut cpu = create_cpu();
+        cpu.v[0] = (HIRES_WIDTH - 8) as u8;
+        cpu.i = 0x300;
+        cpu.ram[0x300..0x320].fill(0xFF);
+        cpu.load_rom(&[0x00, 0xFF, 0xD0, 0x10]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+        assert!(cpu.vram.hires());
+        cpu.tick(NO_KEYS).unwrap();
+
+        let lit = cpu.vram.pixels().filter(|on| *on).count();
+        assert_eq!(lit, 16 * 16);
+        assert!(cpu.vram.get(HIRES_WIDTH - 1, 15));
+        assert!(cpu.vram.get(7, 15), "Sprite must wrap around");
+    }
+
+    #[test]
+    fn test_00fd_exit() {
+        let mut cpu = create_cpu();
+        cpu.load_rom(&[0x00, 0xFD]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert!(cpu.halted());
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn test_fx30_hires_font() {
+        let mut cpu = create_cpu();
+        cpu.v[2] = 1;
+        cpu.load_rom(&[0xF2, 0x30]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.i as usize, HIRES_FONTS_START + 10);
+        assert_eq!(
+            cpu.ram[cpu.i as usize..cpu.i as usize + 10],
+            HIRES_FONTS[10..20]
+        );
+    }
+
+    #[test]
+    fn test_fx75_fx85_rpl_flags() {
+        let mut cpu = create_cpu();
+        cpu.v[0] = 1;
+        cpu.v[1] = 2;
+        cpu.load_rom(&[0xF1, 0x75, 0x60, 0x00, 0xF1, 0x85]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+        cpu.tick(NO_KEYS).unwrap();
+        assert_eq!(cpu.v[0], 0);
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.v[..2], [1, 2]);
+        assert_eq!(cpu.rpl_flags()[..3], [1, 2, 0]);
+    }
+
+    #[test]
+    fn test_f000_long_load() {
+        let mut cpu = create_cpu();
+        cpu.load_rom(&[0xF0, 0x00, 0xAB, 0xCD]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.i, 0xABCD);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn test_skip_over_f000() {
+        let mut cpu = create_cpu();
+        cpu.load_rom(&[0x30, 0x00, 0xF0, 0x00, 0xAB, 0xCD]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn test_5xy2_5xy3_register_range() {
+        let mut cpu = create_cpu();
+        cpu.i = 0x300;
+        cpu.v[1] = 1;
+        cpu.v[2] = 2;
+        cpu.v[3] = 3;
+        // SAVE V1 - V3; LOAD V6 - V4
+        cpu.load_rom(&[0x51, 0x32, 0x56, 0x43]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+        assert_eq!(cpu.ram[0x300..0x304], [1, 2, 3, 0]);
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.v[4..7], [3, 2, 1]);
+        assert_eq!(cpu.i, 0x300);
+    }
+
+    #[test]
+    fn test_fn01_draw_two_planes() {
+        let mut cpu = create_cpu();
+        cpu.i = 0x300;
+        cpu.ram[0x300] = 0x80;
+        cpu.ram[0x301] = 0xC0;
+        // PLANE 3; DRW V0, V0, 1
+        cpu.load_rom(&[0xF3, 0x01, 0xD0, 0x01]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.vram.color(0, 0), 0b11);
+        assert_eq!(cpu.vram.color(1, 0), 0b10);
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn test_00dn_scroll_up() {
+        let mut cpu = create_cpu();
+        cpu.vram.set(0, 3, true);
+        cpu.load_rom(&[0x00, 0xD2]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert!(cpu.vram.get(0, 1));
+        assert!(!cpu.vram.get(0, 3));
+    }
+
+    #[test]
+    fn test_f002_fx3a_audio() {
+        let mut cpu = create_cpu();
+        cpu.i = 0x300;
+        cpu.ram[0x300..0x310].fill(0xAA);
+        cpu.v[1] = 112;
+        cpu.load_rom(&[0xF0, 0x02, 0xF1, 0x3A]).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+        assert!(cpu.refresh_audio());
+        assert_eq!(cpu.audio_pattern(), Some(&[0xAA; 16]));
+        cpu.tick(NO_KEYS).unwrap();
+
+        assert_eq!(cpu.pitch(), 112);
+    }
+
+    #[test]
+    fn test_exa1_skips() {
+        let mut cpu = create_cpu();
+
+        cpu.v[1] = 3;
+        let rom: &[u8] = &[0xe1, 0xa1];
+        cpu.load_rom(rom).unwrap();
+
+        let keys = KeyState::from(0b0101);
+        cpu.tick(&keys).unwrap();
+
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn test_fx0a_waits_for_release() {
+        let mut cpu = create_cpu();
+        let rom: &[u8] = &[0xf3, 0x0a];
+        cpu.load_rom(rom).unwrap();
+
+        cpu.tick(NO_KEYS).unwrap();
+        assert_eq!(cpu.pc, 0x200, "Wait for a key press");
 
-        let keys = vec![Key::Num0, Key::Num2];
-        cpu.tick(keys);
+        let mut keys = KeyState::new();
+        keys.set(0xB, true);
+        cpu.tick(&keys).unwrap();
+        cpu.tick(&keys).unwrap();
+        assert_eq!(cpu.pc, 0x200, "Wait for the key release");
 
+        cpu.tick(NO_KEYS).unwrap();
         assert_eq!(cpu.pc, 0x202);
+        assert_eq!(cpu.v[3], 0xB);
     }
 }
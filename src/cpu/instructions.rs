@@ -1,14 +1,26 @@
+use std::fmt;
+
 use super::Opcode;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
-    Nop,
+    Sys { nnn: u16 },
     Cls,
     Rts,
+    Scd { n: u8 },
+    Scu { n: u8 },
+    Scr,
+    Scl,
+    Exit,
+    Low,
+    High,
     Jmp { nnn: u16 },
     Call { nnn: u16 },
     Ske { x: u8, kk: u8 },
     Skne { x: u8, kk: u8 },
     Skre { x: u8, y: u8 },
+    Srng { x: u8, y: u8 },
+    Lrng { x: u8, y: u8 },
     Load { x: u8, kk: u8 },
     Add { x: u8, kk: u8 },
     Move { x: u8, y: u8 },
@@ -18,9 +30,11 @@ pub enum Instruction {
     Addr { x: u8, y: u8 },
     Sub { x: u8, y: u8 },
     Shr { x: u8, y: u8 },
+    Subn { x: u8, y: u8 },
     Shl { x: u8, y: u8 },
     Skrne { x: u8, y: u8 },
     Loadi { nnn: u16 },
+    Ldil,
     Jumpi { nnn: u16 },
     Rand { x: u8, kk: u8 },
     Draw { x: u8, y: u8, n: u8 },
@@ -28,28 +42,44 @@ pub enum Instruction {
     Skup { x: u8 },
     Moved { x: u8 },
     Keyd { x: u8 },
+    Plane { n: u8 },
+    Audio,
     Loadd { x: u8 },
     Loads { x: u8 },
     Addi { x: u8 },
     Ldspr { x: u8 },
+    Ldhf { x: u8 },
+    Pitch { x: u8 },
     Bcd { x: u8 },
     Stor { x: u8 },
     Read { x: u8 },
+    Srpl { x: u8 },
+    Lrpl { x: u8 },
 }
 
 impl Instruction {
-    pub fn decode(opcode: Opcode) -> Instruction {
+    /// Decode an opcode, returning `None` if it is not a known instruction.
+    pub fn decode(opcode: &Opcode) -> Option<Instruction> {
         let (x, y, n, kk, nnn) = opcode.interpret();
 
-        match opcode.nibbles() {
+        let instruction = match opcode.nibbles() {
             (0x0, 0x0, 0xE, 0x0) => Instruction::Cls,
             (0x0, 0x0, 0xE, 0xE) => Instruction::Rts,
-            (0x0, _, _, _) => Instruction::Nop,
+            (0x0, 0x0, 0xC, _) => Instruction::Scd { n },
+            (0x0, 0x0, 0xD, _) => Instruction::Scu { n },
+            (0x0, 0x0, 0xF, 0xB) => Instruction::Scr,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::Scl,
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::Low,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::High,
+            (0x0, _, _, _) => Instruction::Sys { nnn },
             (0x1, _, _, _) => Instruction::Jmp { nnn },
             (0x2, _, _, _) => Instruction::Call { nnn },
             (0x3, _, _, _) => Instruction::Ske { x, kk },
             (0x4, _, _, _) => Instruction::Skne { x, kk },
-            (0x5, _, _, _) => Instruction::Skre { x, y },
+            (0x5, _, _, 0x0) => Instruction::Skre { x, y },
+            (0x5, _, _, 0x2) => Instruction::Srng { x, y },
+            (0x5, _, _, 0x3) => Instruction::Lrng { x, y },
             (0x6, _, _, _) => Instruction::Load { x, kk },
             (0x7, _, _, _) => Instruction::Add { x, kk },
             (0x8, _, _, 0x0) => Instruction::Move { x, y },
@@ -59,6 +89,7 @@ impl Instruction {
             (0x8, _, _, 0x4) => Instruction::Addr { x, y },
             (0x8, _, _, 0x5) => Instruction::Sub { x, y },
             (0x8, _, _, 0x6) => Instruction::Shr { x, y },
+            (0x8, _, _, 0x7) => Instruction::Subn { x, y },
             (0x8, _, _, 0xE) => Instruction::Shl { x, y },
             (0x9, _, _, _) => Instruction::Skrne { x, y },
             (0xA, _, _, _) => Instruction::Loadi { nnn },
@@ -67,16 +98,187 @@ impl Instruction {
             (0xD, _, _, _) => Instruction::Draw { x, y, n },
             (0xE, _, 0x9, 0xE) => Instruction::Skpr { x },
             (0xE, _, 0xA, 0x1) => Instruction::Skup { x },
+            (0xF, 0x0, 0x0, 0x0) => Instruction::Ldil,
+            (0xF, _, 0x0, 0x1) => Instruction::Plane { n: x },
+            (0xF, 0x0, 0x0, 0x2) => Instruction::Audio,
             (0xF, _, 0x0, 0x7) => Instruction::Moved { x },
             (0xF, _, 0x0, 0xA) => Instruction::Keyd { x },
             (0xF, _, 0x1, 0x5) => Instruction::Loadd { x },
             (0xF, _, 0x1, 0x8) => Instruction::Loads { x },
             (0xF, _, 0x1, 0xE) => Instruction::Addi { x },
             (0xF, _, 0x2, 0x9) => Instruction::Ldspr { x },
+            (0xF, _, 0x3, 0x0) => Instruction::Ldhf { x },
             (0xF, _, 0x3, 0x3) => Instruction::Bcd { x },
+            (0xF, _, 0x3, 0xA) => Instruction::Pitch { x },
             (0xF, _, 0x5, 0x5) => Instruction::Stor { x },
             (0xF, _, 0x6, 0x5) => Instruction::Read { x },
-            _ => panic!("Unknown opcode: {}", opcode),
+            (0xF, _, 0x7, 0x5) => Instruction::Srpl { x },
+            (0xF, _, 0x8, 0x5) => Instruction::Lrpl { x },
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+}
+
+impl Instruction {
+    /// Assembly mnemonic, formatting addresses with `address`, e.g. to print labels.
+    pub fn mnemonic(&self, address: impl Fn(u16) -> String) -> String {
+        match *self {
+            Instruction::Sys { nnn } => format!("SYS {}", address(nnn)),
+            Instruction::Cls => "CLS".to_string(),
+            Instruction::Rts => "RET".to_string(),
+            Instruction::Scd { n } => format!("SCD {}", n),
+            Instruction::Scu { n } => format!("SCU {}", n),
+            Instruction::Scr => "SCR".to_string(),
+            Instruction::Scl => "SCL".to_string(),
+            Instruction::Exit => "EXIT".to_string(),
+            Instruction::Low => "LOW".to_string(),
+            Instruction::High => "HIGH".to_string(),
+            Instruction::Jmp { nnn } => format!("JP {}", address(nnn)),
+            Instruction::Call { nnn } => format!("CALL {}", address(nnn)),
+            Instruction::Ske { x, kk } => format!("SE V{:X}, {:#04X}", x, kk),
+            Instruction::Skne { x, kk } => format!("SNE V{:X}, {:#04X}", x, kk),
+            Instruction::Skre { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            Instruction::Srng { x, y } => format!("SAVE V{:X}, V{:X}", x, y),
+            Instruction::Lrng { x, y } => format!("LOAD V{:X}, V{:X}", x, y),
+            Instruction::Load { x, kk } => format!("LD V{:X}, {:#04X}", x, kk),
+            Instruction::Add { x, kk } => format!("ADD V{:X}, {:#04X}", x, kk),
+            Instruction::Move { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            Instruction::Addr { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            Instruction::Shr { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            Instruction::Subn { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            Instruction::Shl { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            Instruction::Skrne { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            Instruction::Loadi { nnn } => format!("LD I, {}", address(nnn)),
+            Instruction::Ldil => "LD I, LONG".to_string(),
+            Instruction::Jumpi { nnn } => format!("JP V0, {}", address(nnn)),
+            Instruction::Rand { x, kk } => format!("RND V{:X}, {:#04X}", x, kk),
+            Instruction::Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Skpr { x } => format!("SKP V{:X}", x),
+            Instruction::Skup { x } => format!("SKNP V{:X}", x),
+            Instruction::Moved { x } => format!("LD V{:X}, DT", x),
+            Instruction::Keyd { x } => format!("LD V{:X}, K", x),
+            Instruction::Plane { n } => format!("PLANE {}", n),
+            Instruction::Audio => "AUDIO".to_string(),
+            Instruction::Loadd { x } => format!("LD DT, V{:X}", x),
+            Instruction::Loads { x } => format!("LD ST, V{:X}", x),
+            Instruction::Addi { x } => format!("ADD I, V{:X}", x),
+            Instruction::Ldspr { x } => format!("LD F, V{:X}", x),
+            Instruction::Ldhf { x } => format!("LD HF, V{:X}", x),
+            Instruction::Pitch { x } => format!("PITCH V{:X}", x),
+            Instruction::Bcd { x } => format!("LD B, V{:X}", x),
+            Instruction::Stor { x } => format!("LD [I], V{:X}", x),
+            Instruction::Read { x } => format!("LD V{:X}, [I]", x),
+            Instruction::Srpl { x } => format!("LD R, V{:X}", x),
+            Instruction::Lrpl { x } => format!("LD V{:X}, R", x),
+        }
+    }
+}
+
+impl Instruction {
+    /// Encode back into an opcode, the inverse of [`Instruction::decode`].
+    pub fn encode(&self) -> u16 {
+        let xkk = |op: u16, x: u8, kk: u8| op << 12 | (x as u16) << 8 | kk as u16;
+        let xyn = |op: u16, x: u8, y: u8, n: u8| xkk(op, x, y << 4 | n);
+
+        match *self {
+            Instruction::Sys { nnn } => nnn,
+            Instruction::Cls => 0x00E0,
+            Instruction::Rts => 0x00EE,
+            Instruction::Scd { n } => 0x00C0 | n as u16,
+            Instruction::Scu { n } => 0x00D0 | n as u16,
+            Instruction::Scr => 0x00FB,
+            Instruction::Scl => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Low => 0x00FE,
+            Instruction::High => 0x00FF,
+            Instruction::Jmp { nnn } => 0x1000 | nnn,
+            Instruction::Call { nnn } => 0x2000 | nnn,
+            Instruction::Ske { x, kk } => xkk(0x3, x, kk),
+            Instruction::Skne { x, kk } => xkk(0x4, x, kk),
+            Instruction::Skre { x, y } => xyn(0x5, x, y, 0x0),
+            Instruction::Srng { x, y } => xyn(0x5, x, y, 0x2),
+            Instruction::Lrng { x, y } => xyn(0x5, x, y, 0x3),
+            Instruction::Load { x, kk } => xkk(0x6, x, kk),
+            Instruction::Add { x, kk } => xkk(0x7, x, kk),
+            Instruction::Move { x, y } => xyn(0x8, x, y, 0x0),
+            Instruction::Or { x, y } => xyn(0x8, x, y, 0x1),
+            Instruction::And { x, y } => xyn(0x8, x, y, 0x2),
+            Instruction::Xor { x, y } => xyn(0x8, x, y, 0x3),
+            Instruction::Addr { x, y } => xyn(0x8, x, y, 0x4),
+            Instruction::Sub { x, y } => xyn(0x8, x, y, 0x5),
+            Instruction::Shr { x, y } => xyn(0x8, x, y, 0x6),
+            Instruction::Subn { x, y } => xyn(0x8, x, y, 0x7),
+            Instruction::Shl { x, y } => xyn(0x8, x, y, 0xE),
+            Instruction::Skrne { x, y } => xyn(0x9, x, y, 0x0),
+            Instruction::Loadi { nnn } => 0xA000 | nnn,
+            Instruction::Ldil => 0xF000,
+            Instruction::Jumpi { nnn } => 0xB000 | nnn,
+            Instruction::Rand { x, kk } => xkk(0xC, x, kk),
+            Instruction::Draw { x, y, n } => xyn(0xD, x, y, n),
+            Instruction::Skpr { x } => xkk(0xE, x, 0x9E),
+            Instruction::Skup { x } => xkk(0xE, x, 0xA1),
+            Instruction::Moved { x } => xkk(0xF, x, 0x07),
+            Instruction::Keyd { x } => xkk(0xF, x, 0x0A),
+            Instruction::Plane { n } => xkk(0xF, n, 0x01),
+            Instruction::Audio => 0xF002,
+            Instruction::Loadd { x } => xkk(0xF, x, 0x15),
+            Instruction::Loads { x } => xkk(0xF, x, 0x18),
+            Instruction::Addi { x } => xkk(0xF, x, 0x1E),
+            Instruction::Ldspr { x } => xkk(0xF, x, 0x29),
+            Instruction::Ldhf { x } => xkk(0xF, x, 0x30),
+            Instruction::Pitch { x } => xkk(0xF, x, 0x3A),
+            Instruction::Bcd { x } => xkk(0xF, x, 0x33),
+            Instruction::Stor { x } => xkk(0xF, x, 0x55),
+            Instruction::Read { x } => xkk(0xF, x, 0x65),
+            Instruction::Srpl { x } => xkk(0xF, x, 0x75),
+            Instruction::Lrpl { x } => xkk(0xF, x, 0x85),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = self.mnemonic(|address| format!("{:#05X}", address));
+        write!(f, "{}", mnemonic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble(opcode: u16) -> String {
+        let opcode = Opcode::try_from(&[opcode][..]).unwrap();
+        Instruction::decode(&opcode).unwrap().to_string()
+    }
+
+    #[test]
+    fn mnemonics() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x1234), "JP 0x234");
+        assert_eq!(disassemble(0x632A), "LD V3, 0x2A");
+        assert_eq!(disassemble(0x8AB4), "ADD VA, VB");
+        assert_eq!(disassemble(0x8127), "SUBN V1, V2");
+        assert_eq!(disassemble(0xB300), "JP V0, 0x300");
+        assert_eq!(disassemble(0xD015), "DRW V0, V1, 5");
+        assert_eq!(disassemble(0xF265), "LD V2, [I]");
+        assert_eq!(disassemble(0x5123), "LOAD V1, V2");
+    }
+
+    #[test]
+    fn encode_every_opcode() {
+        for raw in 0..=u16::MAX {
+            let opcode = Opcode::try_from(&[raw][..]).unwrap();
+            if let Some(instruction) = Instruction::decode(&opcode) {
+                let encoded = Opcode::try_from(&[instruction.encode()][..]).unwrap();
+                assert_eq!(Instruction::decode(&encoded), Some(instruction));
+            }
         }
     }
 }
@@ -0,0 +1,253 @@
+//! Save states: the complete [`Cpu`] state in a versioned binary format.
+//!
+//! The format is a `C8SS` magic, a big-endian `u16` version and the fields in
+//! the order of [`Cpu::snapshot`], multi-byte values being big-endian. The RAM
+//! comes last, up to the end of the part in use, the rest being zero.
+
+use super::{Cpu, RAM_SIZE};
+use crate::constants::{HIRES_HEIGHT, HIRES_WIDTH};
+use crate::error::{Chip8Error, Result};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::vram::{Vram, PLANES};
+
+const MAGIC: &[u8; 4] = b"C8SS";
+/// Incremented on every change of the format
+const VERSION: u16 = 1;
+
+fn invalid(reason: impl Into<String>) -> Chip8Error {
+    Chip8Error::InvalidSnapshot {
+        reason: reason.into(),
+    }
+}
+
+/// Cursor over a snapshot, failing on truncated data.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid("truncated data"));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(invalid(format!("invalid boolean {}", value))),
+        }
+    }
+}
+
+impl Cpu {
+    /// Serialise the complete state, to [`restore`](Cpu::restore) it later.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.ram_used + HIRES_WIDTH * HIRES_HEIGHT + 128);
+        out.extend(MAGIC);
+        out.extend(VERSION.to_be_bytes());
+
+        for address in self.stack.iter() {
+            out.extend(address.to_be_bytes());
+        }
+        out.extend(self.v);
+        out.extend(self.i.to_be_bytes());
+        out.extend((self.pc as u16).to_be_bytes());
+        out.push(self.sp as u8);
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.extend(self.rpl);
+        out.push(self.halted as u8);
+        // 0xFF when not waiting, keys being 0 to 0xF
+        out.push(self.awaited_key.unwrap_or(0xFF));
+        out.push(self.audio_pattern.is_some() as u8);
+        out.extend(self.audio_pattern.unwrap_or_default());
+        out.push(self.pitch);
+        out.push(self.drawn_this_frame as u8);
+
+        out.push(self.quirks.bits());
+
+        out.push(self.vram.hires() as u8);
+        out.push(self.vram.planes());
+        for column in self.vram.raw() {
+            out.extend(column);
+        }
+
+        out.extend(self.rng.seed().to_be_bytes());
+        out.extend(self.rng.state().to_be_bytes());
+
+        out.extend(&self.ram[..self.ram_used]);
+
+        out
+    }
+
+    /// Restore a state saved by [`snapshot`](Cpu::snapshot), leaving the CPU
+    /// untouched if it is invalid.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<()> {
+        let mut reader = Reader(snapshot);
+        if reader.bytes(MAGIC.len()).ok() != Some(MAGIC) {
+            return Err(invalid("not a save state"));
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported version {}", version)));
+        }
+
+        let mut cpu = Cpu::default();
+        for address in cpu.stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        cpu.v = reader.array()?;
+        cpu.i = reader.u16()?;
+        cpu.pc = reader.u16()? as usize;
+        cpu.sp = reader.u8()? as usize;
+        if cpu.sp > cpu.stack.len() {
+            return Err(invalid(format!("stack pointer {} out of bounds", cpu.sp)));
+        }
+        cpu.delay_timer = reader.u8()?;
+        cpu.sound_timer = reader.u8()?;
+        cpu.rpl = reader.array()?;
+        cpu.halted = reader.bool()?;
+        cpu.awaited_key = match reader.u8()? {
+            0xFF => None,
+            key => Some(key),
+        };
+        let has_pattern = reader.bool()?;
+        let pattern = reader.array()?;
+        cpu.audio_pattern = has_pattern.then_some(pattern);
+        cpu.pitch = reader.u8()?;
+        cpu.drawn_this_frame = reader.bool()?;
+
+        cpu.quirks = Quirks::from_bits(reader.u8()?);
+
+        let hires = reader.bool()?;
+        let planes = reader.u8()?;
+        if planes >= 1 << PLANES {
+            return Err(invalid(format!("invalid planes {:#b}", planes)));
+        }
+        let mut pixels = [[0; HIRES_HEIGHT]; HIRES_WIDTH];
+        for column in pixels.iter_mut() {
+            column.copy_from_slice(reader.bytes(HIRES_HEIGHT)?);
+        }
+        cpu.vram = Vram::from_raw(hires, planes, pixels);
+
+        let seed = reader.u64()?;
+        cpu.rng = Rng::from_parts(seed, reader.u64()?);
+
+        let ram = reader.0;
+        if ram.len() > RAM_SIZE {
+            return Err(invalid("trailing data"));
+        }
+        cpu.ram[..ram.len()].copy_from_slice(ram);
+        cpu.ram_used = ram.len();
+
+        // Redraw the restored screen and reload the audio pattern
+        cpu.vram_changed = true;
+        cpu.audio_changed = cpu.audio_pattern.is_some();
+        *self = cpu;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::KeyState;
+
+    // LD V0, 5; LD I, 0x20E; DRW V0, V0, 5; CALL 0x20A; JP 0x208; ADD V1, 1; RET
+    const ROM: &[u8] = &[
+        0x60, 0x05, 0xA2, 0x0E, 0xD0, 0x05, 0x22, 0x0A, 0x12, 0x08, 0x71, 0x01, 0x00, 0xEE, 0xFF,
+    ];
+
+    fn run(cpu: &mut Cpu, ticks: usize) {
+        for _ in 0..ticks {
+            cpu.tick(&KeyState::new()).unwrap();
+        }
+    }
+
+    #[test]
+    fn fork_execution() {
+        let mut cpu = Cpu::default();
+        cpu.set_quirks(Quirks::SUPER_CHIP);
+        cpu.load_rom(ROM).unwrap();
+        run(&mut cpu, 5);
+        let snapshot = cpu.snapshot();
+
+        run(&mut cpu, 4);
+        let expected = (cpu.pc(), cpu.v()[1], cpu.sp(), cpu.snapshot());
+
+        let mut fork = Cpu::default();
+        fork.restore(&snapshot).unwrap();
+        assert_eq!(fork.quirks(), Quirks::SUPER_CHIP);
+        assert_eq!(fork.stack(), &[0x206]);
+        assert!(fork.vram().get(5, 5));
+        run(&mut fork, 4);
+
+        assert_eq!(
+            (fork.pc(), fork.v()[1], fork.sp(), fork.snapshot()),
+            expected
+        );
+    }
+
+    #[test]
+    fn restore_random_generator() {
+        let mut cpu = Cpu::default();
+        cpu.set_seed(7);
+        // RND V0, 0xFF; JP 0x200
+        cpu.load_rom(&[0xC0, 0xFF, 0x12, 0x00]).unwrap();
+        run(&mut cpu, 2);
+        let snapshot = cpu.snapshot();
+        run(&mut cpu, 1);
+        let expected = cpu.v()[0];
+
+        let mut fork = Cpu::default();
+        fork.restore(&snapshot).unwrap();
+        run(&mut fork, 1);
+
+        assert_eq!(fork.seed(), 7);
+        assert_eq!(fork.v()[0], expected);
+    }
+
+    #[test]
+    fn reject_invalid_snapshots() {
+        let mut cpu = Cpu::default();
+        cpu.load_rom(ROM).unwrap();
+        let snapshot = cpu.snapshot();
+
+        let mut other = Cpu::default();
+        assert!(other.restore(b"garbage").is_err());
+        assert!(other.restore(&snapshot[..100]).is_err());
+        let mut larger = snapshot.clone();
+        larger.resize(snapshot.len() - 0x20F + RAM_SIZE + 1, 0);
+        assert!(other.restore(&larger).is_err());
+
+        let mut newer = snapshot.clone();
+        newer[5] = 99;
+        let err = other.restore(&newer).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid save state: unsupported version 99"
+        );
+
+        assert_eq!(other.ram()[0x200], 0, "Left untouched");
+    }
+}
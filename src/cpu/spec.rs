@@ -0,0 +1,396 @@
+//! Table-driven specification of every instruction.
+//!
+//! Each [`Spec`] gives the state before an instruction, its opcode and the
+//! state after it, under one or several quirks profiles. The instruction is
+//! placed at the program counter and executed once; the expected state is the
+//! initial one with the listed changes and the program counter advanced by 2.
+//! Anything else changing, in the registers, the RAM or the screen, fails the
+//! spec.
+
+use super::{Cpu, HIRES_FONTS_START};
+use crate::keyboard::KeyState;
+use crate::quirks::Quirks;
+
+use std::collections::HashSet;
+use std::mem;
+
+use super::instructions::Instruction;
+use super::Opcode;
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    V(u8, u8),
+    I(u16),
+    Pc(u16),
+    /// Return addresses, the most recent one last
+    Stack(&'static [u16]),
+    Ram(u16, &'static [u8]),
+    Delay(u8),
+    Sound(u8),
+    /// Lit pixels, in the selected planes
+    Lit(&'static [(usize, usize)]),
+    Hires(bool),
+    Planes(u8),
+    Halted(bool),
+    Awaited(Option<u8>),
+    Rpl(&'static [u8]),
+    Pattern([u8; 16]),
+    Pitch(u8),
+}
+
+use State::*;
+
+struct Spec {
+    name: &'static str,
+    opcode: &'static [u8],
+    given: &'static [State],
+    expect: &'static [State],
+    keys: u16,
+    profiles: &'static [Quirks],
+}
+
+/// Specs not depending on quirks hold under every profile.
+const ANY: &[Quirks] = &[
+    Quirks::COSMAC_VIP,
+    Quirks::CHIP_48,
+    Quirks::SUPER_CHIP,
+    Quirks::MODERN,
+];
+
+const fn spec(
+    name: &'static str,
+    opcode: &'static [u8],
+    given: &'static [State],
+    expect: &'static [State],
+) -> Spec {
+    Spec {
+        name,
+        opcode,
+        given,
+        expect,
+        keys: 0,
+        profiles: ANY,
+    }
+}
+
+impl Spec {
+    const fn keys(mut self, keys: u16) -> Spec {
+        self.keys = keys;
+        self
+    }
+
+    const fn profiles(mut self, profiles: &'static [Quirks]) -> Spec {
+        self.profiles = profiles;
+        self
+    }
+}
+
+#[rustfmt::skip]
+const SPECS: &[Spec] = &[
+    spec("SYS is ignored", &[0x01, 0x23], &[], &[]),
+    spec("CLS", &[0x00, 0xE0], &[Lit(&[(0, 0), (63, 31)])], &[Lit(&[])]),
+    spec("RET", &[0x00, 0xEE], &[Stack(&[0x300, 0x400])], &[Stack(&[0x300]), Pc(0x402)]),
+    spec("SCD", &[0x00, 0xC2], &[Lit(&[(0, 0), (5, 31)])], &[Lit(&[(0, 2)])]),
+    spec("SCU", &[0x00, 0xD2], &[Lit(&[(0, 0), (5, 3)])], &[Lit(&[(5, 1)])]),
+    spec("SCR", &[0x00, 0xFB], &[Lit(&[(0, 0), (62, 1)])], &[Lit(&[(4, 0)])]),
+    spec("SCL", &[0x00, 0xFC], &[Lit(&[(8, 0), (1, 1)])], &[Lit(&[(4, 0)])]),
+    spec("SCR in high resolution", &[0x00, 0xFB], &[Hires(true), Lit(&[(100, 60)])], &[Lit(&[(104, 60)])]),
+    spec("EXIT halts", &[0x00, 0xFD], &[], &[Halted(true), Pc(0x200)]),
+    spec("LOW clears the screen", &[0x00, 0xFE], &[Hires(true), Lit(&[(100, 0)])], &[Hires(false), Lit(&[])]),
+    spec("HIGH clears the screen", &[0x00, 0xFF], &[Lit(&[(1, 1)])], &[Hires(true), Lit(&[])]),
+    spec("JP", &[0x1A, 0xBC], &[], &[Pc(0xABC)]),
+    spec("CALL", &[0x24, 0x00], &[], &[Stack(&[0x200]), Pc(0x400)]),
+    spec("CALL nested", &[0x25, 0x00], &[Pc(0x400), Stack(&[0x200])], &[Stack(&[0x200, 0x400]), Pc(0x500)]),
+    spec("SE skips", &[0x33, 0x2A], &[V(3, 0x2A)], &[Pc(0x204)]),
+    spec("SE does not skip", &[0x33, 0x2A], &[V(3, 0x2B)], &[]),
+    spec("SE skips over LD I, LONG", &[0x30, 0x00, 0xF0, 0x00], &[], &[Pc(0x206)]),
+    spec("SNE skips", &[0x43, 0x2A], &[V(3, 0x2B)], &[Pc(0x204)]),
+    spec("SNE does not skip", &[0x43, 0x2A], &[V(3, 0x2A)], &[]),
+    spec("SE Vx, Vy skips", &[0x54, 0x50], &[V(4, 2), V(5, 2)], &[Pc(0x204)]),
+    spec("SE Vx, Vy does not skip", &[0x54, 0x50], &[V(4, 2), V(5, 3)], &[]),
+    spec("SAVE", &[0x51, 0x32], &[I(0x300), V(1, 1), V(2, 2), V(3, 3)], &[Ram(0x300, &[1, 2, 3])]),
+    spec("SAVE in reverse order", &[0x53, 0x12], &[I(0x300), V(1, 1), V(2, 2), V(3, 3)], &[Ram(0x300, &[3, 2, 1])]),
+    spec("LOAD", &[0x54, 0x63], &[I(0x300), Ram(0x300, &[1, 2, 3])], &[V(4, 1), V(5, 2), V(6, 3)]),
+    spec("LOAD in reverse order", &[0x56, 0x43], &[I(0x300), Ram(0x300, &[1, 2, 3])], &[V(4, 3), V(5, 2), V(6, 1)]),
+    spec("LD Vx, byte", &[0x63, 0x2A], &[], &[V(3, 0x2A)]),
+    spec("LD VF, byte", &[0x6F, 0x2A], &[], &[V(0xF, 0x2A)]),
+    spec("ADD Vx, byte", &[0x71, 0x02], &[V(1, 3)], &[V(1, 5)]),
+    spec("ADD Vx, byte wraps without carry", &[0x71, 0x02], &[V(1, 0xFF)], &[V(1, 1)]),
+    spec("LD Vx, Vy", &[0x84, 0x50], &[V(5, 2)], &[V(4, 2)]),
+    spec("LD VF, Vy", &[0x8F, 0x10], &[V(1, 5)], &[V(0xF, 5)]),
+    spec("OR", &[0x84, 0x51], &[V(4, 0b1001), V(5, 0b1010), V(0xF, 7)], &[V(4, 0b1011)])
+        .profiles(&[Quirks::MODERN, Quirks::SUPER_CHIP]),
+    spec("OR resets VF", &[0x84, 0x51], &[V(4, 0b1001), V(5, 0b1010), V(0xF, 7)], &[V(4, 0b1011), V(0xF, 0)])
+        .profiles(&[Quirks::COSMAC_VIP]),
+    spec("OR into VF", &[0x8F, 0x11], &[V(1, 0b0110), V(0xF, 0b1001)], &[V(0xF, 0b1111)])
+        .profiles(&[Quirks::MODERN]),
+    spec("AND", &[0x84, 0x52], &[V(4, 0b1001), V(5, 0b1010), V(0xF, 7)], &[V(4, 0b1000)])
+        .profiles(&[Quirks::MODERN, Quirks::SUPER_CHIP]),
+    spec("AND resets VF", &[0x84, 0x52], &[V(4, 0b1001), V(5, 0b1010), V(0xF, 7)], &[V(4, 0b1000), V(0xF, 0)])
+        .profiles(&[Quirks::COSMAC_VIP]),
+    spec("XOR", &[0x84, 0x53], &[V(4, 0b1001), V(5, 0b1010), V(0xF, 7)], &[V(4, 0b0011)])
+        .profiles(&[Quirks::MODERN, Quirks::SUPER_CHIP]),
+    spec("XOR resets VF", &[0x84, 0x53], &[V(4, 0b1001), V(5, 0b1010), V(0xF, 7)], &[V(4, 0b0011), V(0xF, 0)])
+        .profiles(&[Quirks::COSMAC_VIP]),
+    spec("XOR with itself", &[0x84, 0x43], &[V(4, 0xAA)], &[V(4, 0)])
+        .profiles(&[Quirks::MODERN]),
+    spec("ADD Vx, Vy", &[0x84, 0x54], &[V(4, 1), V(5, 2), V(0xF, 7)], &[V(4, 3), V(0xF, 0)]),
+    spec("ADD Vx, Vy carries", &[0x84, 0x54], &[V(4, 0xFF), V(5, 2)], &[V(4, 1), V(0xF, 1)]),
+    spec("ADD Vx, Vx", &[0x84, 0x44], &[V(4, 0x80)], &[V(4, 0), V(0xF, 1)]),
+    spec("SUB", &[0x84, 0x55], &[V(4, 5), V(5, 3)], &[V(4, 2), V(0xF, 1)]),
+    spec("SUB borrows", &[0x84, 0x55], &[V(4, 3), V(5, 5), V(0xF, 7)], &[V(4, 0xFE), V(0xF, 0)]),
+    spec("SUB without borrow when equal", &[0x84, 0x55], &[V(4, 3), V(5, 3)], &[V(4, 0), V(0xF, 1)]),
+    spec("SUBN", &[0x84, 0x57], &[V(4, 3), V(5, 5)], &[V(4, 2), V(0xF, 1)]),
+    spec("SUBN borrows", &[0x84, 0x57], &[V(4, 5), V(5, 3), V(0xF, 7)], &[V(4, 0xFE), V(0xF, 0)]),
+    spec("ADD Vx, VF", &[0x84, 0xF4], &[V(4, 0xFF), V(0xF, 2)], &[V(4, 1), V(0xF, 1)]),
+    spec("ADD VF, Vy keeps the flag", &[0x8F, 0x14], &[V(1, 2), V(0xF, 0xFF)], &[V(0xF, 1)]),
+    spec("SUB Vx, VF", &[0x84, 0xF5], &[V(4, 5), V(0xF, 3)], &[V(4, 2), V(0xF, 1)]),
+    spec("SUB VF, Vy keeps the flag", &[0x8F, 0x15], &[V(1, 6), V(0xF, 5)], &[V(0xF, 0)]),
+    spec("SUBN Vx, VF", &[0x84, 0xF7], &[V(4, 3), V(0xF, 5)], &[V(4, 2), V(0xF, 1)]),
+    spec("SUBN VF, Vy keeps the flag", &[0x8F, 0x17], &[V(1, 5), V(0xF, 3)], &[V(0xF, 1)]),
+    spec("SHR Vy", &[0x84, 0x56], &[V(4, 0xF0), V(5, 0b0011)], &[V(4, 0b0001), V(0xF, 1)])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::MODERN]),
+    spec("SHR Vx in place", &[0x84, 0x56], &[V(4, 0b1000), V(5, 0b0011), V(0xF, 7)], &[V(4, 0b0100), V(0xF, 0)])
+        .profiles(&[Quirks::CHIP_48, Quirks::SUPER_CHIP]),
+    spec("SHL Vy", &[0x84, 0x5E], &[V(4, 0x0F), V(5, 0x81)], &[V(4, 0x02), V(0xF, 1)])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::MODERN]),
+    spec("SHL Vx in place", &[0x84, 0x5E], &[V(4, 0x40), V(5, 0x81), V(0xF, 7)], &[V(4, 0x80), V(0xF, 0)])
+        .profiles(&[Quirks::CHIP_48, Quirks::SUPER_CHIP]),
+    spec("SHR Vx, VF", &[0x84, 0xF6], &[V(0xF, 0b0011)], &[V(4, 0b0001), V(0xF, 1)])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::MODERN]),
+    spec("SHR VF keeps the flag", &[0x8F, 0x16], &[V(0xF, 0b0010)], &[V(0xF, 0)])
+        .profiles(&[Quirks::CHIP_48, Quirks::SUPER_CHIP]),
+    spec("SHL Vx, VF", &[0x84, 0xFE], &[V(0xF, 0xC1)], &[V(4, 0x82), V(0xF, 1)])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::MODERN]),
+    spec("SHL VF keeps the flag", &[0x8F, 0x1E], &[V(0xF, 0x40)], &[V(0xF, 0)])
+        .profiles(&[Quirks::CHIP_48, Quirks::SUPER_CHIP]),
+    spec("SNE Vx, Vy skips", &[0x94, 0x50], &[V(4, 2), V(5, 3)], &[Pc(0x204)]),
+    spec("SNE Vx, Vy does not skip", &[0x94, 0x50], &[V(4, 2), V(5, 2)], &[]),
+    spec("LD I, addr", &[0xA1, 0x23], &[], &[I(0x123)]),
+    spec("LD I, LONG", &[0xF0, 0x00, 0xAB, 0xCD], &[], &[I(0xABCD), Pc(0x204)]),
+    spec("JP V0, addr", &[0xB3, 0x00], &[V(0, 0x10), V(3, 0x02)], &[Pc(0x310)])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::MODERN]),
+    spec("JP Vx, addr", &[0xB3, 0x00], &[V(0, 0x10), V(3, 0x02)], &[Pc(0x302)])
+        .profiles(&[Quirks::CHIP_48, Quirks::SUPER_CHIP]),
+    spec("RND with an empty mask", &[0xC0, 0x00], &[V(0, 0xFF)], &[V(0, 0)]),
+    spec("DRW", &[0xD0, 0x15], &[I(0), V(0, 1), V(1, 2), V(0xF, 7)], &[
+        V(0xF, 0),
+        Lit(&[
+            (1, 2), (2, 2), (3, 2), (4, 2),
+            (1, 3), (4, 3),
+            (1, 4), (4, 4),
+            (1, 5), (4, 5),
+            (1, 6), (2, 6), (3, 6), (4, 6),
+        ]),
+    ]),
+    spec("DRW collides", &[0xD0, 0x11], &[I(0), Lit(&[(0, 0), (5, 0)])], &[V(0xF, 1), Lit(&[(1, 0), (2, 0), (3, 0), (5, 0)])]),
+    spec("DRW wraps the coordinates", &[0xD0, 0x11], &[I(0), V(0, 64 + 1), V(1, 32)], &[V(0xF, 0), Lit(&[(1, 0), (2, 0), (3, 0), (4, 0)])]),
+    spec("DRW wraps around the edges", &[0xD0, 0x12], &[I(0), V(0, 62), V(1, 31)], &[
+        V(0xF, 0),
+        Lit(&[(62, 31), (63, 31), (0, 31), (1, 31), (62, 0), (1, 0)]),
+    ])
+        .profiles(&[Quirks::MODERN]),
+    spec("DRW clips at the edges", &[0xD0, 0x12], &[I(0), V(0, 62), V(1, 31)], &[V(0xF, 0), Lit(&[(62, 31), (63, 31)])])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::SUPER_CHIP]),
+    spec("DRW at VF", &[0xDF, 0xF1], &[I(0), V(0xF, 2)], &[V(0xF, 0), Lit(&[(2, 2), (3, 2), (4, 2), (5, 2)])]),
+    spec("DRW 16x16 in high resolution", &[0xD0, 0x10], &[Hires(true), I(0x300), V(0, 120), Ram(0x300, &[0x80, 0x01])], &[
+        V(0xF, 0),
+        Lit(&[(120, 0), (7, 0)]),
+    ])
+        .profiles(&[Quirks::MODERN]),
+    spec("DRW in two planes", &[0xD0, 0x01], &[Planes(0b11), I(0x300), Ram(0x300, &[0x80, 0xC0])], &[
+        V(0xF, 0),
+        Lit(&[(0, 0)]),
+        Planes(0b10),
+        Lit(&[(0, 0), (1, 0)]),
+        Planes(0b11),
+    ]),
+    spec("SKP skips", &[0xE1, 0x9E], &[V(1, 2)], &[Pc(0x204)]).keys(0b0100),
+    spec("SKP does not skip", &[0xE1, 0x9E], &[V(1, 3)], &[]).keys(0b0100),
+    spec("SKNP skips", &[0xE1, 0xA1], &[V(1, 3)], &[Pc(0x204)]).keys(0b0100),
+    spec("SKNP does not skip", &[0xE1, 0xA1], &[V(1, 2)], &[]).keys(0b0100),
+    spec("LD Vx, DT", &[0xF3, 0x07], &[Delay(5)], &[V(3, 5)]),
+    spec("LD Vx, K waits for a key", &[0xF3, 0x0A], &[], &[Pc(0x200)]),
+    spec("LD Vx, K waits for the release", &[0xF3, 0x0A], &[], &[Awaited(Some(0xB)), Pc(0x200)]).keys(1 << 0xB),
+    spec("LD Vx, K stores the released key", &[0xF3, 0x0A], &[Awaited(Some(0xB))], &[Awaited(None), V(3, 0xB)]),
+    spec("PLANE", &[0xF2, 0x01], &[], &[Planes(0b10)]),
+    spec("AUDIO", &[0xF0, 0x02], &[I(0x300), Ram(0x300, &[0xAA; 16])], &[Pattern([0xAA; 16])]),
+    spec("LD DT, Vx", &[0xF3, 0x15], &[V(3, 5)], &[Delay(5)]),
+    spec("LD ST, Vx", &[0xF3, 0x18], &[V(3, 5)], &[Sound(5)]),
+    spec("ADD I, Vx", &[0xF3, 0x1E], &[I(0x300), V(3, 5)], &[I(0x305)]),
+    spec("ADD I, Vx wraps", &[0xF3, 0x1E], &[I(0xFFFF), V(3, 2), V(0xF, 7)], &[I(1)]),
+    spec("LD F, Vx", &[0xF3, 0x29], &[V(3, 0xA)], &[I(0xA * 5)]),
+    spec("LD F, Vx uses the low nibble", &[0xF3, 0x29], &[V(3, 0xFA)], &[I(0xA * 5)]),
+    spec("LD HF, Vx", &[0xF3, 0x30], &[V(3, 1)], &[I(HIRES_FONTS_START as u16 + 10)]),
+    spec("PITCH", &[0xF3, 0x3A], &[V(3, 112)], &[Pitch(112)]),
+    spec("LD B, Vx", &[0xF3, 0x33], &[I(0x300), V(3, 137)], &[Ram(0x300, &[1, 3, 7])]),
+    spec("LD B, Vx of 255", &[0xF3, 0x33], &[I(0x300), V(3, 255)], &[Ram(0x300, &[2, 5, 5])]),
+    spec("LD B, Vx of 7", &[0xF3, 0x33], &[I(0x300), V(3, 7), Ram(0x300, &[9, 9, 9])], &[Ram(0x300, &[0, 0, 7])]),
+    spec("LD [I], Vx", &[0xF2, 0x55], &[I(0x300), V(0, 1), V(1, 2), V(2, 3), V(3, 4)], &[Ram(0x300, &[1, 2, 3]), I(0x303)])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::MODERN]),
+    spec("LD [I], Vx leaves I", &[0xF2, 0x55], &[I(0x300), V(0, 1), V(1, 2), V(2, 3), V(3, 4)], &[Ram(0x300, &[1, 2, 3])])
+        .profiles(&[Quirks::SUPER_CHIP]),
+    spec("LD [I], VF at the top of the RAM wraps I", &[0xFF, 0x55], &[I(0xFFF0), V(0xF, 0xFF)], &[Ram(0xFFFF, &[0xFF]), I(0)])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::MODERN]),
+    spec("LD [I], VF", &[0xFF, 0x55], &[I(0x300), V(0xF, 0xFF)], &[Ram(0x30F, &[0xFF])])
+        .profiles(&[Quirks::SUPER_CHIP]),
+    spec("LD Vx, [I]", &[0xF2, 0x65], &[I(0x300), Ram(0x300, &[1, 2, 3, 4])], &[V(0, 1), V(1, 2), V(2, 3), I(0x303)])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::MODERN]),
+    spec("LD VF, [I] at the top of the RAM wraps I", &[0xFF, 0x65], &[I(0xFFF0), Ram(0xFFFF, &[7])], &[V(0xF, 7), I(0)])
+        .profiles(&[Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::MODERN]),
+    spec("LD Vx, [I] leaves I", &[0xF2, 0x65], &[I(0x300), Ram(0x300, &[1, 2, 3, 4])], &[V(0, 1), V(1, 2), V(2, 3)])
+        .profiles(&[Quirks::SUPER_CHIP]),
+    spec("LD R, Vx", &[0xF1, 0x75], &[V(0, 1), V(1, 2), V(2, 3)], &[Rpl(&[1, 2])]),
+    spec("LD Vx, R", &[0xF1, 0x85], &[Rpl(&[1, 2, 3]), V(2, 9)], &[V(0, 1), V(1, 2)]),
+];
+
+impl State {
+    fn apply(&self, cpu: &mut Cpu) {
+        match *self {
+            V(x, value) => cpu.v[x as usize] = value,
+            I(i) => cpu.i = i,
+            Pc(pc) => cpu.pc = pc as usize,
+            Stack(stack) => {
+                cpu.stack[..stack.len()].copy_from_slice(stack);
+                cpu.stack[stack.len()..].fill(0);
+                cpu.sp = stack.len();
+            }
+            Ram(address, bytes) => {
+                let address = address as usize;
+                cpu.ram[address..address + bytes.len()].copy_from_slice(bytes);
+            }
+            Delay(value) => cpu.delay_timer = value,
+            Sound(value) => cpu.sound_timer = value,
+            Lit(pixels) => {
+                cpu.vram.clear();
+                for &(x, y) in pixels {
+                    cpu.vram.set(x, y, true);
+                }
+            }
+            Hires(hires) => cpu.vram.set_hires(hires),
+            Planes(planes) => cpu.vram.select_planes(planes),
+            Halted(halted) => cpu.halted = halted,
+            Awaited(key) => cpu.awaited_key = key,
+            Rpl(rpl) => cpu.rpl[..rpl.len()].copy_from_slice(rpl),
+            Pattern(pattern) => cpu.audio_pattern = Some(pattern),
+            Pitch(pitch) => cpu.pitch = pitch,
+        }
+    }
+}
+
+/// Everything an instruction may change, RAM being compared separately.
+#[derive(Debug, PartialEq)]
+struct Observed {
+    v: [u8; 16],
+    i: u16,
+    pc: usize,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    halted: bool,
+    awaited_key: Option<u8>,
+    rpl: [u8; 16],
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    hires: bool,
+    planes: u8,
+    /// Coordinates and colour of the lit pixels
+    pixels: Vec<(usize, usize, u8)>,
+}
+
+impl Observed {
+    fn new(cpu: &Cpu) -> Observed {
+        let vram = &cpu.vram;
+        let pixels = (0..vram.height())
+            .flat_map(|y| (0..vram.width()).map(move |x| (x, y, vram.color(x, y))))
+            .filter(|(_, _, color)| *color != 0)
+            .collect();
+
+        Observed {
+            v: cpu.v,
+            i: cpu.i,
+            pc: cpu.pc,
+            stack: cpu.stack().to_vec(),
+            delay_timer: cpu.delay_timer,
+            sound_timer: cpu.sound_timer,
+            halted: cpu.halted,
+            awaited_key: cpu.awaited_key,
+            rpl: cpu.rpl,
+            audio_pattern: cpu.audio_pattern,
+            pitch: cpu.pitch,
+            hires: vram.hires(),
+            planes: vram.planes(),
+            pixels,
+        }
+    }
+}
+
+/// CPU in the given state, with the instruction at the program counter.
+fn prepare(spec: &Spec, quirks: Quirks) -> Cpu {
+    let mut cpu = Cpu::default();
+    cpu.set_quirks(quirks);
+    cpu.set_seed(0);
+    cpu.load_rom(&[]).unwrap();
+    for state in spec.given {
+        state.apply(&mut cpu);
+    }
+    cpu.ram[cpu.pc..cpu.pc + spec.opcode.len()].copy_from_slice(spec.opcode);
+    cpu
+}
+
+#[test]
+fn instructions() {
+    for spec in SPECS {
+        for &quirks in spec.profiles {
+            let context = format!("{} ({}) under {}", spec.name, spec_opcode(spec), quirks);
+
+            let mut expected = prepare(spec, quirks);
+            expected.pc += 2;
+            for state in spec.expect {
+                state.apply(&mut expected);
+            }
+
+            let mut cpu = prepare(spec, quirks);
+            cpu.tick(&KeyState::from(spec.keys))
+                .unwrap_or_else(|err| panic!("{}: {}", context, err));
+
+            assert_eq!(Observed::new(&cpu), Observed::new(&expected), "{}", context);
+            let ram_diff: Vec<(usize, u8)> = (0..cpu.ram.len())
+                .filter(|&address| cpu.ram[address] != expected.ram[address])
+                .map(|address| (address, cpu.ram[address]))
+                .collect();
+            assert!(
+                ram_diff.is_empty(),
+                "{}: unexpected RAM {:X?}",
+                context,
+                ram_diff
+            );
+        }
+    }
+}
+
+fn spec_opcode(spec: &Spec) -> String {
+    let opcode = Opcode([spec.opcode[0], spec.opcode[1]]);
+    Instruction::decode(&opcode).unwrap().to_string()
+}
+
+/// Every instruction has at least one spec.
+#[test]
+fn coverage() {
+    let decode = |opcode: u16| Instruction::decode(&Opcode(opcode.to_be_bytes()));
+    let specified: HashSet<_> = SPECS
+        .iter()
+        .filter_map(|spec| decode(u16::from_be_bytes([spec.opcode[0], spec.opcode[1]])))
+        .map(|instruction| mem::discriminant(&instruction))
+        .collect();
+
+    for opcode in 0..=0xFFFF {
+        if let Some(instruction) = decode(opcode) {
+            assert!(
+                specified.contains(&mem::discriminant(&instruction)),
+                "No spec for {:?}",
+                instruction
+            );
+        }
+    }
+}
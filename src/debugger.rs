@@ -0,0 +1,506 @@
+//! Interactive step debugger, driven from a REPL.
+//!
+//! The [`Machine`](crate::Machine) calls the debugger before and after every
+//! instruction. Execution starts paused; the debugger stops again when stepping
+//! is over, at a breakpoint, on an instruction matching an opcode pattern or
+//! after a watched register or memory location changed.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use crate::cpu::Cpu;
+
+const HELP: &str = "\
+Commands:
+  s, step [N]         execute N instructions (default 1)
+  c, continue         run until the next break
+  b, break ADDR       break at address ADDR
+  bo, breakop PATTERN break on opcodes matching PATTERN, `_` matching any nibble (e.g. D__5)
+  w, watch TARGET     break when TARGET changes: a register (V0..VF, I) or a memory address
+  l, list             list breakpoints and watches
+  d, delete           delete all breakpoints and watches
+  r, regs             dump registers, stack and timers
+  m, mem ADDR [LEN]   hexdump LEN bytes of memory starting at ADDR (default 40)
+  h, help             show this help
+  q, quit             exit the emulator
+Addresses and lengths are hexadecimal, with or without a 0x prefix.";
+
+/// Register or memory location observed by a watch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchTarget {
+    Register(u8),
+    I,
+    Memory(u16),
+}
+
+impl WatchTarget {
+    fn read(&self, cpu: &Cpu) -> u16 {
+        match self {
+            WatchTarget::Register(x) => cpu.v()[*x as usize] as u16,
+            WatchTarget::I => cpu.i(),
+            WatchTarget::Memory(address) => cpu.ram()[*address as usize] as u16,
+        }
+    }
+}
+
+impl FromStr for WatchTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_uppercase();
+        if upper == "I" {
+            return Ok(WatchTarget::I);
+        }
+        if let Some(x) = upper.strip_prefix('V') {
+            if x.len() == 1 {
+                if let Ok(x) = u8::from_str_radix(x, 16) {
+                    return Ok(WatchTarget::Register(x));
+                }
+            }
+        }
+        parse_address(s).map(WatchTarget::Memory)
+    }
+}
+
+impl fmt::Display for WatchTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchTarget::Register(x) => write!(f, "V{:X}", x),
+            WatchTarget::I => write!(f, "I"),
+            WatchTarget::Memory(address) => write!(f, "[{:#05X}]", address),
+        }
+    }
+}
+
+/// Opcode with wildcard nibbles, such as `D__5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodePattern {
+    value: u16,
+    mask: u16,
+}
+
+impl OpcodePattern {
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+impl FromStr for OpcodePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.chars().count() != 4 {
+            return Err(format!("Invalid opcode pattern: {}", s));
+        }
+
+        let mut pattern = OpcodePattern { value: 0, mask: 0 };
+        for c in s.chars() {
+            pattern.value <<= 4;
+            pattern.mask <<= 4;
+            match c.to_digit(16) {
+                Some(nibble) => {
+                    pattern.value |= nibble as u16;
+                    pattern.mask |= 0xF;
+                }
+                None if "_.XYNKxynk".contains(c) => (),
+                None => return Err(format!("Invalid opcode pattern: {}", s)),
+            }
+        }
+        Ok(pattern)
+    }
+}
+
+impl fmt::Display for OpcodePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for shift in [12, 8, 4, 0] {
+            if (self.mask >> shift) & 0xF == 0 {
+                write!(f, "_")?;
+            } else {
+                write!(f, "{:X}", (self.value >> shift) & 0xF)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Step(u32),
+    Continue,
+    Break(u16),
+    BreakOpcode(OpcodePattern),
+    Watch(WatchTarget),
+    List,
+    Delete,
+    Registers,
+    Memory { address: u16, len: u16 },
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let arg = words.next();
+
+        let missing = || format!("Missing argument to `{}`", command);
+        let command = match command {
+            "s" | "step" => match arg {
+                Some(n) => match n.parse() {
+                    Ok(n) if n > 0 => Command::Step(n),
+                    _ => return Err(format!("Invalid number of steps: {}", n)),
+                },
+                None => Command::Step(1),
+            },
+            "c" | "continue" => Command::Continue,
+            "b" | "break" => Command::Break(parse_address(arg.ok_or_else(missing)?)?),
+            "bo" | "breakop" => Command::BreakOpcode(arg.ok_or_else(missing)?.parse()?),
+            "w" | "watch" => Command::Watch(arg.ok_or_else(missing)?.parse()?),
+            "l" | "list" => Command::List,
+            "d" | "delete" => Command::Delete,
+            "r" | "regs" => Command::Registers,
+            "m" | "mem" => Command::Memory {
+                address: parse_address(arg.ok_or_else(missing)?)?,
+                len: match words.next() {
+                    Some(len) => {
+                        parse_hex(len).ok_or_else(|| format!("Invalid length: {}", len))?
+                    }
+                    None => 0x40,
+                },
+            },
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(format!("Unknown command `{}`, try `help`", command)),
+        };
+        Ok(command)
+    }
+}
+
+fn parse_address(s: &str) -> Result<u16, String> {
+    parse_hex(s).ok_or_else(|| format!("Invalid address: {}", s))
+}
+
+fn parse_hex(s: &str) -> Option<u16> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+pub struct Debugger {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+
+    breakpoints: BTreeSet<u16>,
+    patterns: Vec<OpcodePattern>,
+    /// Watched targets with their last seen values
+    watches: Vec<(WatchTarget, u16)>,
+
+    /// Instructions left to execute before breaking, `None` when continuing
+    steps: Option<u32>,
+    /// Changes reported by the watches, to break on before the next instruction
+    changes: Vec<String>,
+}
+
+impl Debugger {
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Debugger {
+        Debugger {
+            input,
+            output,
+            breakpoints: BTreeSet::new(),
+            patterns: vec![],
+            watches: vec![],
+            steps: Some(0),
+            changes: vec![],
+        }
+    }
+
+    /// Debugger reading commands from the standard input.
+    pub fn stdio() -> Debugger {
+        Debugger::new(Box::new(io::stdin().lock()), Box::new(io::stdout()))
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Called before executing each instruction, running the REPL if execution
+    /// must break. Return `false` if the user asked to quit.
+    pub fn before_step(&mut self, cpu: &Cpu) -> io::Result<bool> {
+        if let Some(reason) = self.break_reason(cpu) {
+            writeln!(self.output, "{}", reason)?;
+            if !self.repl(cpu)? {
+                return Ok(false);
+            }
+        }
+
+        if let Some(steps) = self.steps.as_mut() {
+            *steps -= 1;
+        }
+        Ok(true)
+    }
+
+    /// Called after executing each instruction to check the watches.
+    pub fn after_step(&mut self, cpu: &Cpu) {
+        for (target, last) in self.watches.iter_mut() {
+            let value = target.read(cpu);
+            if value != *last {
+                self.changes
+                    .push(format!("Watch {}: {:#X} -> {:#X}", target, last, value));
+                *last = value;
+            }
+        }
+    }
+
+    fn break_reason(&mut self, cpu: &Cpu) -> Option<String> {
+        let pc = cpu.pc();
+        let location = match cpu.opcode() {
+            Some(opcode) => format!("{:#05X}: {:04X}", pc, opcode),
+            None => format!("{:#05X}: ????", pc),
+        };
+
+        if !self.changes.is_empty() {
+            let changes = self.changes.join("\n");
+            self.changes.clear();
+            return Some(format!("{}\n{}", changes, location));
+        }
+        if self.steps == Some(0) {
+            return Some(location);
+        }
+        if self.breakpoints.contains(&pc) {
+            return Some(format!("Breakpoint\n{}", location));
+        }
+        let opcode = cpu.opcode()?;
+        self.patterns
+            .iter()
+            .find(|pattern| pattern.matches(opcode))
+            .map(|pattern| format!("Opcode matches {}\n{}", pattern, location))
+    }
+
+    /// Read and run commands until one resumes the execution.
+    /// Return `false` if the user asked to quit.
+    fn repl(&mut self, cpu: &Cpu) -> io::Result<bool> {
+        loop {
+            write!(self.output, "(chip8) ")?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(false);
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match line.parse() {
+                Err(err) => writeln!(self.output, "{}", err)?,
+                Ok(Command::Step(n)) => {
+                    self.steps = Some(n);
+                    return Ok(true);
+                }
+                Ok(Command::Continue) => {
+                    self.steps = None;
+                    return Ok(true);
+                }
+                Ok(Command::Quit) => return Ok(false),
+                Ok(Command::Break(address)) => {
+                    self.breakpoints.insert(address);
+                }
+                Ok(Command::BreakOpcode(pattern)) => self.patterns.push(pattern),
+                Ok(Command::Watch(target)) => self.watches.push((target, target.read(cpu))),
+                Ok(Command::List) => self.list()?,
+                Ok(Command::Delete) => {
+                    self.breakpoints.clear();
+                    self.patterns.clear();
+                    self.watches.clear();
+                }
+                Ok(Command::Registers) => self.dump_registers(cpu)?,
+                Ok(Command::Memory { address, len }) => self.hexdump(cpu, address, len)?,
+                Ok(Command::Help) => writeln!(self.output, "{}", HELP)?,
+            }
+        }
+    }
+
+    fn list(&mut self) -> io::Result<()> {
+        for address in self.breakpoints.iter() {
+            writeln!(self.output, "Breakpoint {:#05X}", address)?;
+        }
+        for pattern in self.patterns.iter() {
+            writeln!(self.output, "Opcode {}", pattern)?;
+        }
+        for (target, value) in self.watches.iter() {
+            writeln!(self.output, "Watch {} = {:#X}", target, value)?;
+        }
+        Ok(())
+    }
+
+    fn dump_registers(&mut self, cpu: &Cpu) -> io::Result<()> {
+        writeln!(
+            self.output,
+            "PC: {:#05X}  I: {:#05X}  SP: {}  DT: {}  ST: {}",
+            cpu.pc(),
+            cpu.i(),
+            cpu.sp(),
+            cpu.delay_timer(),
+            cpu.sound_timer()
+        )?;
+        for row in cpu.v().chunks(8).enumerate() {
+            let (offset, registers) = row;
+            let registers: Vec<String> = registers
+                .iter()
+                .enumerate()
+                .map(|(x, value)| format!("V{:X}: {:02X}", offset * 8 + x, value))
+                .collect();
+            writeln!(self.output, "{}", registers.join("  "))?;
+        }
+        let stack: Vec<String> = cpu
+            .stack()
+            .iter()
+            .map(|address| format!("{:#05X}", address))
+            .collect();
+        writeln!(self.output, "Stack: [{}]", stack.join(", "))
+    }
+
+    fn hexdump(&mut self, cpu: &Cpu, address: u16, len: u16) -> io::Result<()> {
+        let start = address as usize;
+        let end = (start + len as usize).min(cpu.ram().len());
+        for (line, bytes) in cpu.ram()[start..end].chunks(16).enumerate() {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            writeln!(
+                self.output,
+                "{:04X}: {}",
+                start + line * 16,
+                bytes.join(" ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::backend::{NullAudio, NullClock, NullDisplay, NullInput};
+    use crate::machine::Machine;
+
+    /// Output shared with the test once boxed in the debugger
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run the program with the given debugger commands, returning the output.
+    fn debug(rom: &[u8], commands: &str) -> String {
+        let output = Output::default();
+        let debugger = Debugger::new(
+            Box::new(io::Cursor::new(commands.to_string())),
+            Box::new(output.clone()),
+        );
+        let mut machine = Machine::new(NullDisplay, NullInput, NullAudio, NullClock::default());
+        machine.set_debugger(debugger);
+        machine.load_rom(rom).unwrap();
+
+        while machine.frame().unwrap() {}
+
+        let output = output.0.borrow();
+        String::from_utf8(output.clone()).unwrap()
+    }
+
+    // LD V1, 0x2A; LD I, 0x300; ADD V1, 1; JP 0x204
+    const ROM: &[u8] = &[0x61, 0x2A, 0xA3, 0x00, 0x71, 0x01, 0x12, 0x04];
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!("s".parse(), Ok(Command::Step(1)));
+        assert_eq!("step 10".parse(), Ok(Command::Step(10)));
+        assert_eq!("b 0x2A0".parse(), Ok(Command::Break(0x2A0)));
+        assert_eq!(
+            "w vA".parse(),
+            Ok(Command::Watch(WatchTarget::Register(0xA)))
+        );
+        assert_eq!(
+            "w 300".parse(),
+            Ok(Command::Watch(WatchTarget::Memory(0x300)))
+        );
+        assert_eq!(
+            "m 200 10".parse(),
+            Ok(Command::Memory {
+                address: 0x200,
+                len: 0x10
+            })
+        );
+        assert_eq!(
+            "m 0x200 0x1F".parse(),
+            Ok(Command::Memory {
+                address: 0x200,
+                len: 0x1F
+            })
+        );
+        assert!("step 0".parse::<Command>().is_err());
+        assert!("jump".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn opcode_pattern() {
+        let pattern: OpcodePattern = "D__5".parse().unwrap();
+
+        assert!(pattern.matches(0xD125));
+        assert!(!pattern.matches(0xD126));
+        assert_eq!(pattern.to_string(), "D__5");
+        assert!("D_5".parse::<OpcodePattern>().is_err());
+    }
+
+    #[test]
+    fn step_and_dump_registers() {
+        let output = debug(ROM, "s 2\nr\nq\n");
+
+        assert!(output.starts_with("0x200: 612A\n(chip8) 0x204: 7101\n"));
+        assert!(output.contains("PC: 0x204  I: 0x300  SP: 0  DT: 0  ST: 0\n"));
+        assert!(output.contains("V0: 00  V1: 2A  V2: 00"));
+        assert!(output.contains("Stack: []\n"));
+    }
+
+    #[test]
+    fn breakpoint() {
+        let output = debug(ROM, "b 206\nc\nq\n");
+
+        assert!(output.contains("Breakpoint\n0x206: 1204\n"));
+    }
+
+    #[test]
+    fn break_on_opcode() {
+        let output = debug(ROM, "bo 7___\nc\nc\nq\n");
+
+        assert!(output.contains("Opcode matches 7___\n0x204: 7101\n"));
+    }
+
+    #[test]
+    fn watch_register() {
+        let output = debug(ROM, "w v1\nc\nc\nq\n");
+
+        assert!(output.contains("Watch V1: 0x0 -> 0x2A\n0x202: A300\n"));
+        assert!(output.contains("Watch V1: 0x2A -> 0x2B\n0x206: 1204\n"));
+    }
+
+    #[test]
+    fn hexdump() {
+        let output = debug(ROM, "m 200 14\nq\n");
+
+        assert!(output.contains("0200: 61 2A A3 00 71 01 12 04 00 00 00 00 00 00 00 00\n"));
+        assert!(output.contains("0210: 00 00 00 00\n"));
+    }
+}
@@ -0,0 +1,298 @@
+//! Recursive-descent disassembler.
+//!
+//! Starting from the entry point, the disassembler follows every jump, call and
+//! skip to tell the code apart from the data, mostly sprites, that the ROM
+//! contains. Jump and call targets get `L` labels and the addresses loaded into
+//! I get `D` labels. The listing can be fed back to the assembler.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::cpu::instructions::Instruction;
+use crate::cpu::{Opcode, RAM_SIZE, ROM_START};
+use crate::error::{Chip8Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Data,
+    Code,
+}
+
+struct Disassembler<'a> {
+    rom: &'a [u8],
+    /// Addresses of the instructions reached from the entry point, with their length
+    code: BTreeMap<u16, u16>,
+    labels: BTreeMap<u16, Label>,
+}
+
+impl<'a> Disassembler<'a> {
+    fn new(rom: &'a [u8]) -> Disassembler<'a> {
+        Disassembler {
+            rom,
+            code: BTreeMap::new(),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Address following the last byte of the ROM, up to 0x10000.
+    fn end(&self) -> usize {
+        ROM_START + self.rom.len()
+    }
+
+    fn fetch(&self, address: u16) -> Option<u16> {
+        let offset = (address as usize).checked_sub(ROM_START)?;
+        let bytes = self.rom.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn decode(&self, address: u16) -> Option<(u16, Instruction)> {
+        let opcode = self.fetch(address)?;
+        let instruction = Instruction::decode(&Opcode::try_from(&[opcode][..]).ok()?)?;
+        Some((opcode, instruction))
+    }
+
+    fn label(&mut self, address: u16, label: Label) {
+        let entry = self.labels.entry(address).or_insert(label);
+        *entry = label.max(*entry);
+    }
+
+    /// Follow every path from the entry point, recording the instructions met.
+    fn trace(&mut self) {
+        let mut todo = vec![ROM_START as u16];
+        self.label(ROM_START as u16, Label::Code);
+
+        while let Some(address) = todo.pop() {
+            if self.code.contains_key(&address) {
+                continue;
+            }
+            let Some((_, instruction)) = self.decode(address) else {
+                continue;
+            };
+            let next = address.wrapping_add(2);
+            let mut len = 2;
+
+            match instruction {
+                Instruction::Jmp { nnn } => {
+                    self.label(nnn, Label::Code);
+                    todo.push(nnn);
+                }
+                Instruction::Call { nnn } => {
+                    self.label(nnn, Label::Code);
+                    todo.extend([next, nnn]);
+                }
+                // The target depends on V0, only the start of the jump table is known
+                Instruction::Jumpi { nnn } => {
+                    self.label(nnn, Label::Code);
+                    todo.push(nnn);
+                }
+                Instruction::Rts | Instruction::Exit => (),
+                Instruction::Ske { .. }
+                | Instruction::Skne { .. }
+                | Instruction::Skre { .. }
+                | Instruction::Skrne { .. }
+                | Instruction::Skpr { .. }
+                | Instruction::Skup { .. } => {
+                    let skipped = if self.fetch(next) == Some(0xF000) {
+                        4
+                    } else {
+                        2
+                    };
+                    todo.extend([next.wrapping_add(skipped), next]);
+                }
+                Instruction::Loadi { nnn } => {
+                    self.label(nnn, Label::Data);
+                    todo.push(next);
+                }
+                Instruction::Ldil => {
+                    // Truncated at the end of the ROM, listed as data
+                    let Some(nnnn) = self.fetch(next) else {
+                        continue;
+                    };
+                    len = 4;
+                    self.label(nnnn, Label::Data);
+                    todo.push(next.wrapping_add(2));
+                }
+                _ => todo.push(next),
+            }
+
+            self.code.insert(address, len);
+        }
+    }
+
+    /// Name of the label at `address`, or the address itself if none is emitted there.
+    fn address(&self, emitted: &BTreeSet<u16>, address: u16) -> String {
+        match self.labels.get(&address) {
+            Some(label) if emitted.contains(&address) => label_name(address, *label),
+            _ => format!("{:#05X}", address),
+        }
+    }
+
+    /// Addresses starting a line of the listing: instructions and data bytes.
+    fn emitted(&self) -> BTreeSet<u16> {
+        let mut emitted = BTreeSet::new();
+        let mut address = ROM_START;
+        while address < self.end() {
+            emitted.insert(address as u16);
+            address += match self.code.get(&(address as u16)) {
+                Some(&len) if address + len as usize <= self.end() => len as usize,
+                _ => 1,
+            };
+        }
+        emitted
+    }
+
+    fn listing(&self) -> String {
+        let emitted = self.emitted();
+        let mut out = String::new();
+
+        for address in emitted.iter().copied() {
+            if let Some(label) = self.labels.get(&address) {
+                writeln!(out, "{}:", label_name(address, *label)).unwrap();
+            }
+
+            let offset = address as usize - ROM_START;
+            match (self.code.get(&address), self.decode(address)) {
+                (Some(4), Some((opcode, Instruction::Ldil))) => {
+                    let nnnn = self.fetch(address + 2).unwrap_or_default();
+                    let mnemonic = format!("LD I, LONG {}", self.address(&emitted, nnnn));
+                    writeln!(
+                        out,
+                        "    {:<24}; {:03X}: {:04X} {:04X}",
+                        mnemonic, address, opcode, nnnn
+                    )
+                    .unwrap();
+                }
+                (Some(2), Some((opcode, instruction))) => {
+                    let mnemonic = instruction.mnemonic(|nnn| self.address(&emitted, nnn));
+                    writeln!(out, "    {:<24}; {:03X}: {:04X}", mnemonic, address, opcode).unwrap();
+                }
+                _ => {
+                    let byte = self.rom[offset];
+                    let sprite: String = (0..8)
+                        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                        .collect();
+                    let data = format!("db {:#04X}", byte);
+                    writeln!(out, "    {:<24}; {:03X}: {}", data, address, sprite).unwrap();
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn label_name(address: u16, label: Label) -> String {
+    match label {
+        Label::Code => format!("L{:03X}", address),
+        Label::Data => format!("D{:03X}", address),
+    }
+}
+
+/// Disassemble a ROM loaded at 0x200 into an assembly listing.
+pub fn disassemble(rom: &[u8]) -> Result<String> {
+    let max = RAM_SIZE - ROM_START;
+    if rom.len() > max {
+        return Err(Chip8Error::RomTooLarge {
+            size: rom.len(),
+            max,
+        });
+    }
+
+    let mut disassembler = Disassembler::new(rom);
+    disassembler.trace();
+    Ok(disassembler.listing())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separate_code_from_data() {
+        let rom = [
+            0xA2, 0x08, // LD I, D208
+            0x22, 0x0A, // CALL L20A
+            0x12, 0x04, // JP L204
+            0x00, 0x00, // unreachable
+            0xF0, 0x90, // sprite
+            0xD0, 0x12, // DRW V0, V1, 2
+            0x00, 0xEE, // RET
+        ];
+
+        let listing = disassemble(&rom).unwrap();
+        let lines: Vec<&str> = listing.lines().map(|line| line.trim_end()).collect();
+
+        assert_eq!(
+            lines,
+            [
+                "L200:",
+                "    LD I, D208              ; 200: A208",
+                "    CALL L20A               ; 202: 220A",
+                "L204:",
+                "    JP L204                 ; 204: 1204",
+                "    db 0x00                 ; 206: ........",
+                "    db 0x00                 ; 207: ........",
+                "D208:",
+                "    db 0xF0                 ; 208: ####....",
+                "    db 0x90                 ; 209: #..#....",
+                "L20A:",
+                "    DRW V0, V1, 2           ; 20A: D012",
+                "    RET                     ; 20C: 00EE",
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_long_load() {
+        let rom = [
+            0x30, 0x01, // SE V0, 0x01
+            0xF0, 0x00, // LD I, LONG D208
+            0x02, 0x08, //
+            0x00, 0xFD, // EXIT
+            0xFF, // sprite
+        ];
+
+        let listing = disassemble(&rom).unwrap();
+
+        assert!(listing.contains("    LD I, LONG D208         ; 202: F000 0208\n"));
+        assert!(
+            !listing.contains("; 204:"),
+            "The skip jumps over both words"
+        );
+        assert!(listing.contains("    EXIT                    ; 206: 00FD\n"));
+        assert!(listing.contains("D208:\n    db 0xFF"));
+    }
+
+    #[test]
+    fn truncated_long_load() {
+        let rom = [0x12, 0x02, 0xF0, 0x00];
+
+        let listing = disassemble(&rom).unwrap();
+
+        assert!(listing.ends_with(
+            "    db 0xF0                 ; 202: ####....\n    db 0x00                 ; 203: ........\n"
+        ));
+        assert_eq!(crate::asm::assemble(&listing).unwrap(), rom);
+    }
+
+    #[test]
+    fn whole_ram() {
+        let mut rom = vec![0; RAM_SIZE - ROM_START];
+        rom[..6].copy_from_slice(&[0xF0, 0x00, 0xFF, 0xFF, 0x00, 0xFD]); // LD I, LONG DFFFF
+
+        let listing = disassemble(&rom).unwrap();
+
+        assert!(listing.starts_with("L200:\n    LD I, LONG DFFFF"));
+        assert!(listing.ends_with("DFFFF:\n    db 0x00                 ; FFFF: ........\n"));
+        assert_eq!(listing.matches("db 0x00").count(), rom.len() - 6);
+
+        rom.push(0);
+        assert!(matches!(
+            disassemble(&rom),
+            Err(Chip8Error::RomTooLarge {
+                size: 65025,
+                max: 65024
+            })
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+use std::{error, fmt, io};
+
+/// Errors raised while loading or running a ROM.
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// The ROM does not fit in the RAM available from 0x200.
+    RomTooLarge { size: usize, max: usize },
+    /// The ROM could not be read.
+    Io(io::Error),
+    /// The opcode at `address` is not a CHIP8 instruction.
+    UnknownOpcode { opcode: u16, address: u16 },
+    /// A subroutine was called with the stack already full.
+    StackOverflow { address: u16 },
+    /// A return was executed with the stack empty.
+    StackUnderflow { address: u16 },
+    /// The instruction at `address` accessed memory past the end of the RAM via I.
+    MemoryOutOfBounds { i: u16, address: u16 },
+    /// The program counter points past the end of the RAM.
+    PcOutOfBounds { address: u16 },
+    /// The save state is corrupted or was written by an incompatible version.
+    InvalidSnapshot { reason: String },
+    /// The movie file is invalid at `line`.
+    InvalidMovie { line: usize, message: String },
+    /// The keymap file next to the ROM is invalid.
+    InvalidKeymap { file: String, message: String },
+    /// The configuration file is invalid.
+    InvalidConfig { file: String, message: String },
+    /// The assembly source is invalid at `line` of `file`.
+    Assembly {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Chip8Error>;
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM too large: {} bytes, maximum is {}", size, max)
+            }
+            Chip8Error::Io(err) => write!(f, "I/O error: {}", err),
+            Chip8Error::UnknownOpcode { opcode, address } => {
+                write!(f, "unknown opcode {:04X} at {:#05X}", opcode, address)
+            }
+            Chip8Error::StackOverflow { address } => {
+                write!(f, "stack overflow at {:#05X}", address)
+            }
+            Chip8Error::StackUnderflow { address } => {
+                write!(f, "stack underflow at {:#05X}", address)
+            }
+            Chip8Error::MemoryOutOfBounds { i, address } => write!(
+                f,
+                "memory access out of bounds with I = {:#05X} at {:#05X}",
+                i, address
+            ),
+            Chip8Error::PcOutOfBounds { address } => {
+                write!(f, "program counter out of bounds: {:#05X}", address)
+            }
+            Chip8Error::InvalidSnapshot { reason } => write!(f, "invalid save state: {}", reason),
+            Chip8Error::InvalidMovie { line, message } => {
+                write!(f, "invalid movie at line {}: {}", line, message)
+            }
+            Chip8Error::InvalidKeymap { file, message } => {
+                write!(f, "invalid keymap {}: {}", file, message)
+            }
+            Chip8Error::InvalidConfig { file, message } => {
+                write!(f, "invalid configuration {}: {}", file, message)
+            }
+            Chip8Error::Assembly {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Chip8Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(err: io::Error) -> Self {
+        Chip8Error::Io(err)
+    }
+}
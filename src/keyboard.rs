@@ -1,5 +1,14 @@
+#[cfg(feature = "sdl")]
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+#[cfg(feature = "sdl")]
+use sdl2::keyboard::{Keycode, Scancode};
+
+#[cfg(feature = "sdl")]
+use crate::backend::Input;
+#[cfg(feature = "sdl")]
+use crate::controller::Controllers;
+#[cfg(feature = "sdl")]
+use crate::keymap::{self, Keymap};
 
 /// Represents available keyboards keys plus the exit one.
 ///
@@ -8,6 +17,7 @@ use sdl2::keyboard::Keycode;
 /// 7  8  9  E
 /// A  0  B  F
 ///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Key {
     Num0,
     Num1,
@@ -77,61 +87,208 @@ impl From<u8> for Key {
     }
 }
 
+/// Emulator commands bound to keys of the frontend, besides the keypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    /// Save the state into the given quick-save slot
+    SaveState(u8),
+    /// Restore the state saved in the given quick-save slot
+    LoadState(u8),
+    /// Start or stop rewinding the gameplay
+    Rewind(bool),
+    /// Switch between windowed and fullscreen mode
+    Fullscreen,
+}
+
+/// Change in the state of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Pressed(Key),
+    Released(Key),
+    Hotkey(Hotkey),
+}
+
+/// Keys currently held down, one bit per CHIP8 key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KeyState(u16);
+
+impl KeyState {
+    pub const fn new() -> KeyState {
+        KeyState(0)
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        key < 16 && self.0 & (1 << key) != 0
+    }
+
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        if key >= 16 {
+            return;
+        }
+        if pressed {
+            self.0 |= 1 << key;
+        } else {
+            self.0 &= !(1 << key);
+        }
+    }
+
+    /// Update the state from an event. [`Key::Exit`] and hotkeys are ignored.
+    pub fn apply(&mut self, event: KeyEvent) {
+        match event {
+            KeyEvent::Pressed(key) => self.set(key.into(), true),
+            KeyEvent::Released(key) => self.set(key.into(), false),
+            KeyEvent::Hotkey(_) => (),
+        }
+    }
+
+    /// Lowest key held down, if any.
+    pub fn first_pressed(&self) -> Option<u8> {
+        (self.0 != 0).then(|| self.0.trailing_zeros() as u8)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+}
+
+impl From<u16> for KeyState {
+    fn from(bits: u16) -> KeyState {
+        KeyState(bits)
+    }
+}
+
+/// Keypad read from the SDL2 window, by position of the keys, and from the
+/// game controllers.
+#[cfg(feature = "sdl")]
 pub struct Keyboard {
     event_pump: sdl2::EventPump,
+    keymap: Vec<(Scancode, Key)>,
+    controllers: Option<Controllers>,
 }
 
+#[cfg(feature = "sdl")]
 impl Keyboard {
     pub fn new(sdl_context: &sdl2::Sdl) -> Keyboard {
         let event_pump = sdl_context.event_pump().unwrap();
+        let controllers = Controllers::new(sdl_context)
+            .map_err(|err| log::warn!("Game controllers unavailable: {}", err))
+            .ok();
 
-        Keyboard { event_pump }
+        let mut keyboard = Keyboard {
+            event_pump,
+            keymap: vec![],
+            controllers,
+        };
+        keyboard.set_keymap(&Keymap::default());
+        keyboard
     }
 
-    /// Return pressed keys since the last call
-    pub fn pressed_keys(&mut self) -> Vec<Key> {
-        let mut keys = vec![];
+    fn map(keymap: &[(Scancode, Key)], scancode: Scancode) -> Option<Key> {
+        keymap
+            .iter()
+            .find(|(bound, _)| *bound == scancode)
+            .map(|(_, key)| *key)
+    }
+
+    /// F1 to F4 save into the quick-save slots, F5 to F8 load them,
+    /// backspace rewinds while held down and F11 toggles fullscreen.
+    fn map_hotkey(keycode: Keycode) -> Option<Hotkey> {
+        let hotkey = match keycode {
+            Keycode::Backspace => Hotkey::Rewind(true),
+            Keycode::F1 => Hotkey::SaveState(1),
+            Keycode::F2 => Hotkey::SaveState(2),
+            Keycode::F3 => Hotkey::SaveState(3),
+            Keycode::F4 => Hotkey::SaveState(4),
+            Keycode::F5 => Hotkey::LoadState(1),
+            Keycode::F6 => Hotkey::LoadState(2),
+            Keycode::F7 => Hotkey::LoadState(3),
+            Keycode::F8 => Hotkey::LoadState(4),
+            Keycode::F11 => Hotkey::Fullscreen,
+            _ => return None,
+        };
+        Some(hotkey)
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl Input for Keyboard {
+    /// Return key events since the last call
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        let mut events = vec![];
 
         for event in self.event_pump.poll_iter() {
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
+                Event::Quit { .. } => events.push(KeyEvent::Pressed(Key::Exit)),
+                Event::KeyDown {
+                    keycode,
+                    scancode: Some(scancode),
+                    repeat: false,
                     ..
                 } => {
-                    keys.push(Key::Exit);
+                    if let Some(key) = Keyboard::map(&self.keymap, scancode) {
+                        events.push(KeyEvent::Pressed(key));
+                    } else if let Some(hotkey) = keycode.and_then(Keyboard::map_hotkey) {
+                        events.push(KeyEvent::Hotkey(hotkey));
+                    }
                 }
-                Event::KeyDown {
-                    keycode: Some(keycode),
+                Event::KeyUp {
+                    keycode,
+                    scancode: Some(scancode),
                     ..
                 } => {
-                    let key = match keycode {
-                        Keycode::X => Some(Key::Num0),
-                        Keycode::Num1 => Some(Key::Num1),
-                        Keycode::Num2 => Some(Key::Num2),
-                        Keycode::Num3 => Some(Key::Num3),
-                        Keycode::Q => Some(Key::Num4),
-                        Keycode::W => Some(Key::Num5),
-                        Keycode::E => Some(Key::Num6),
-                        Keycode::A => Some(Key::Num7),
-                        Keycode::S => Some(Key::Num8),
-                        Keycode::D => Some(Key::Num9),
-                        Keycode::Z => Some(Key::A),
-                        Keycode::B => Some(Key::B),
-                        Keycode::Num4 => Some(Key::C),
-                        Keycode::R => Some(Key::D),
-                        Keycode::F => Some(Key::E),
-                        Keycode::V => Some(Key::F),
-                        _ => None,
-                    };
-                    if let Some(k) = key {
-                        keys.push(k);
-                    };
+                    if let Some(key) = Keyboard::map(&self.keymap, scancode) {
+                        events.push(KeyEvent::Released(key));
+                    } else if keycode == Some(Keycode::Backspace) {
+                        events.push(KeyEvent::Hotkey(Hotkey::Rewind(false)));
+                    }
+                }
+                event => {
+                    if let Some(controllers) = &mut self.controllers {
+                        events.extend(controllers.handle(&event));
+                    }
                 }
-                _ => (),
             };
         }
 
-        keys
+        events
+    }
+
+    fn set_keymap(&mut self, keymap: &Keymap) {
+        self.keymap = keymap
+            .bindings()
+            .iter()
+            .filter_map(|(name, key)| {
+                let scancode = Scancode::from_name(keymap::scancode_name(name)?);
+                if scancode.is_none() {
+                    log::warn!("No scancode for key {}", name);
+                }
+                Some((scancode?, *key))
+            })
+            .collect();
+        if let Some(controllers) = &mut self.controllers {
+            controllers.set_keymap(keymap);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_state() {
+        let mut keys = KeyState::new();
+        assert_eq!(keys.first_pressed(), None);
+
+        keys.apply(KeyEvent::Pressed(Key::C));
+        keys.apply(KeyEvent::Pressed(Key::Num5));
+        keys.apply(KeyEvent::Pressed(Key::Exit));
+        assert!(keys.is_pressed(0xC));
+        assert_eq!(keys.first_pressed(), Some(5));
+        assert_eq!(keys.bits(), 0b0001_0000_0010_0000);
+
+        keys.apply(KeyEvent::Released(Key::Num5));
+        assert!(!keys.is_pressed(5));
+        assert_eq!(keys.first_pressed(), Some(0xC));
     }
 }
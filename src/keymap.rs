@@ -0,0 +1,426 @@
+//! Bindings of the keys of the computer to the CHIP8 keypad.
+//!
+//! Keys are named after their physical position on a QWERTY keyboard, like
+//! SDL2 scancodes, so that a keymap fits any keyboard layout: `Q` is the key
+//! right of Tab, whatever letter it types. Letters and digits are named after
+//! themselves, the other keys are listed in [`NAMED_KEYS`]. Buttons of game
+//! controllers are bound like keys, with the names listed in [`BUTTONS`].
+//!
+//! A keymap is written as bindings `<CHIP8 key>=<key>` separated by commas,
+//! spaces or new lines, e.g. `1=W,4=S,C=Up,D=Down`, the CHIP8 key being a hex
+//! digit or `exit`. The bindings of a CHIP8 key replace the previous ones, and
+//! the name of a [`Preset`] starts over from it. `#` starts a comment.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::keyboard::Key;
+
+const ALPHANUMERIC: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// Names of the keys besides letters and digits, with their SDL2 scancode name.
+pub const NAMED_KEYS: &[(&str, &str)] = &[
+    ("Escape", "Escape"),
+    ("Space", "Space"),
+    ("Return", "Return"),
+    ("Tab", "Tab"),
+    ("Up", "Up"),
+    ("Down", "Down"),
+    ("Left", "Left"),
+    ("Right", "Right"),
+    ("Minus", "-"),
+    ("Equals", "="),
+    ("LeftBracket", "["),
+    ("RightBracket", "]"),
+    ("Semicolon", ";"),
+    ("Apostrophe", "'"),
+    ("Comma", ","),
+    ("Period", "."),
+    ("Slash", "/"),
+    ("KP0", "Keypad 0"),
+    ("KP1", "Keypad 1"),
+    ("KP2", "Keypad 2"),
+    ("KP3", "Keypad 3"),
+    ("KP4", "Keypad 4"),
+    ("KP5", "Keypad 5"),
+    ("KP6", "Keypad 6"),
+    ("KP7", "Keypad 7"),
+    ("KP8", "Keypad 8"),
+    ("KP9", "Keypad 9"),
+    ("KPDivide", "Keypad /"),
+    ("KPMultiply", "Keypad *"),
+    ("KPMinus", "Keypad -"),
+    ("KPPlus", "Keypad +"),
+    ("KPEnter", "Keypad Enter"),
+    ("KPPeriod", "Keypad ."),
+];
+
+/// Names of the game controller buttons, with their SDL2 name.
+pub const BUTTONS: &[(&str, &str)] = &[
+    ("PadUp", "dpup"),
+    ("PadDown", "dpdown"),
+    ("PadLeft", "dpleft"),
+    ("PadRight", "dpright"),
+    ("PadA", "a"),
+    ("PadB", "b"),
+    ("PadX", "x"),
+    ("PadY", "y"),
+    ("PadL", "leftshoulder"),
+    ("PadR", "rightshoulder"),
+    ("PadBack", "back"),
+    ("PadStart", "start"),
+];
+
+/// Game controller buttons bound by every preset
+const CONTROLLER: [(&str, Key); 12] = [
+    ("PadUp", Key::Num2),
+    ("PadDown", Key::Num8),
+    ("PadLeft", Key::Num4),
+    ("PadRight", Key::Num6),
+    ("PadA", Key::Num5),
+    ("PadB", Key::Num0),
+    ("PadX", Key::Num7),
+    ("PadY", Key::Num9),
+    ("PadL", Key::Num1),
+    ("PadR", Key::Num3),
+    ("PadBack", Key::B),
+    ("PadStart", Key::F),
+];
+
+/// Canonical name of a key or button, ignoring the case.
+pub fn key_name(name: &str) -> Option<&'static str> {
+    if name.len() == 1 {
+        let index = ALPHANUMERIC.find(name.to_ascii_uppercase().as_str())?;
+        return Some(&ALPHANUMERIC[index..index + 1]);
+    }
+    NAMED_KEYS
+        .iter()
+        .chain(BUTTONS)
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(key, _)| *key)
+}
+
+/// SDL2 scancode name of a key given its canonical name, `None` for buttons.
+pub fn scancode_name(name: &'static str) -> Option<&'static str> {
+    if button_name(name).is_some() {
+        return None;
+    }
+    let scancode = NAMED_KEYS.iter().find(|(key, _)| *key == name);
+    Some(scancode.map_or(name, |(_, scancode)| *scancode))
+}
+
+/// SDL2 name of a game controller button given its canonical name.
+pub fn button_name(name: &str) -> Option<&'static str> {
+    BUTTONS
+        .iter()
+        .find(|(button, _)| *button == name)
+        .map(|(_, sdl)| *sdl)
+}
+
+/// Built-in keymaps.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preset {
+    /// The left of the keyboard: 1234, QWER, ASDF and ZXCV
+    #[default]
+    Default,
+    /// The numeric keypad, digits on their own key and A to F on / * - + Enter .
+    Keypad,
+}
+
+impl Preset {
+    pub fn keymap(&self) -> Keymap {
+        let keys: [&str; 16] = match self {
+            Preset::Default => [
+                "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+            ],
+            Preset::Keypad => [
+                "KP0",
+                "KP1",
+                "KP2",
+                "KP3",
+                "KP4",
+                "KP5",
+                "KP6",
+                "KP7",
+                "KP8",
+                "KP9",
+                "KPDivide",
+                "KPMultiply",
+                "KPMinus",
+                "KPPlus",
+                "KPEnter",
+                "KPPeriod",
+            ],
+        };
+        let mut bindings: Vec<(&'static str, Key)> = keys
+            .iter()
+            .enumerate()
+            .map(|(value, name)| (key_name(name).unwrap(), Key::from(value as u8)))
+            .collect();
+        bindings.push(("Escape", Key::Exit));
+        bindings.extend(CONTROLLER);
+        Keymap { bindings }
+    }
+
+    fn from_name(name: &str) -> Option<Preset> {
+        <Preset as clap::ValueEnum>::from_str(name, true).ok()
+    }
+}
+
+/// Keys bound to each CHIP8 key, and to [`Key::Exit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(&'static str, Key)>,
+}
+
+impl Keymap {
+    /// CHIP8 key bound to the key of the given canonical name.
+    pub fn key(&self, name: &str) -> Option<Key> {
+        self.bindings
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, chip8)| *chip8)
+    }
+
+    /// Canonical names of the keys with the CHIP8 key they are bound to.
+    pub fn bindings(&self) -> &[(&'static str, Key)] {
+        &self.bindings
+    }
+
+    /// Bind a key, replacing its previous binding.
+    pub fn bind(&mut self, name: &'static str, chip8: Key) {
+        self.bindings.retain(|(key, _)| *key != name);
+        self.bindings.push((name, chip8));
+    }
+
+    /// Apply the bindings and presets of a keymap written as described in the
+    /// [module documentation](self).
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        let mut rebound: Vec<Key> = vec![];
+
+        let entries = spec
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|entry| !entry.is_empty());
+        for entry in entries {
+            let Some((chip8, name)) = entry.split_once('=') else {
+                *self = Preset::from_name(entry)
+                    .ok_or_else(|| format!("unknown preset `{}`", entry))?
+                    .keymap();
+                rebound.clear();
+                continue;
+            };
+
+            let chip8 = match chip8 {
+                _ if chip8.eq_ignore_ascii_case("exit") => Key::Exit,
+                _ => match u8::from_str_radix(chip8, 16) {
+                    Ok(value) if value < 16 && chip8.len() == 1 => Key::from(value),
+                    _ => return Err(format!("invalid CHIP8 key `{}`", chip8)),
+                },
+            };
+            let name = key_name(name).ok_or_else(|| format!("unknown key `{}`", name))?;
+
+            if !rebound.contains(&chip8) {
+                self.bindings.retain(|(_, key)| *key != chip8);
+                rebound.push(chip8);
+            }
+            self.bind(name, chip8);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Preset::default().keymap()
+    }
+}
+
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bindings: Vec<String> = self
+            .bindings
+            .iter()
+            .map(|(name, key)| match key {
+                Key::Exit => format!("exit={}", name),
+                key => format!("{:X}={}", u8::from(*key), name),
+            })
+            .collect();
+        write!(f, "{}", bindings.join(","))
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = String;
+
+    /// Parse a keymap applied over the default preset.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut keymap = Keymap::default();
+        keymap.apply(s)?;
+        Ok(keymap)
+    }
+}
+
+/// Keyboard layouts, to find which key typed a character.
+///
+/// Only the terminal frontend needs it: it receives characters, while the SDL2
+/// window receives the position of the keys.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    #[default]
+    Qwerty,
+    /// French AZERTY
+    Azerty,
+    Dvorak,
+}
+
+/// Characters typed by the keys of the main block without shift, row by row
+const QWERTY: &str = "1234567890-=qwertyuiop[]asdfghjkl;'zxcvbnm,./";
+const AZERTY: &str = "&é\"'(-è_çà)=azertyuiop^$qsdfghjklmùwxcvbn,;:!";
+const DVORAK: &str = "1234567890[]',.pyfgcrl/=aoeuidhtns-;qjkxbmwvz";
+
+/// Names of the keys of the main block, in the order of [`QWERTY`]
+const POSITIONS: [&str; 45] = [
+    "1",
+    "2",
+    "3",
+    "4",
+    "5",
+    "6",
+    "7",
+    "8",
+    "9",
+    "0",
+    "Minus",
+    "Equals",
+    "Q",
+    "W",
+    "E",
+    "R",
+    "T",
+    "Y",
+    "U",
+    "I",
+    "O",
+    "P",
+    "LeftBracket",
+    "RightBracket",
+    "A",
+    "S",
+    "D",
+    "F",
+    "G",
+    "H",
+    "J",
+    "K",
+    "L",
+    "Semicolon",
+    "Apostrophe",
+    "Z",
+    "X",
+    "C",
+    "V",
+    "B",
+    "N",
+    "M",
+    "Comma",
+    "Period",
+    "Slash",
+];
+
+impl Layout {
+    /// Canonical name of the key typing the given character, if it is in the
+    /// main block of the keyboard. Digits typed with shift are found as well.
+    pub fn position(&self, c: char) -> Option<&'static str> {
+        let chars = match self {
+            Layout::Qwerty => QWERTY,
+            Layout::Azerty => AZERTY,
+            Layout::Dvorak => DVORAK,
+        };
+        let c = c.to_lowercase().next().unwrap_or(c);
+        match chars.chars().position(|typed| typed == c) {
+            Some(index) => key_name(POSITIONS[index]),
+            None if c.is_ascii_digit() => key_name(&c.to_string()),
+            None if c == ' ' => Some("Space"),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keymap() {
+        let keymap: Keymap = "1=w, 4=s\nc=up D=down # right paddle".parse().unwrap();
+        assert_eq!(keymap.key("W"), Some(Key::Num1));
+        assert_eq!(keymap.key("S"), Some(Key::Num4));
+        assert_eq!(keymap.key("Up"), Some(Key::C));
+        assert_eq!(keymap.key("Down"), Some(Key::D));
+        // Previously bound to 1, 4, C and D
+        assert_eq!(keymap.key("1"), None);
+        assert_eq!(keymap.key("Q"), None);
+        assert_eq!(keymap.key("4"), None);
+        assert_eq!(keymap.key("R"), None);
+        assert_eq!(keymap.key("X"), Some(Key::Num0));
+        assert_eq!(keymap.key("Escape"), Some(Key::Exit));
+        assert_eq!(keymap.key("PadUp"), Some(Key::Num2));
+
+        let keymap: Keymap = "5=W,5=Up,exit=Tab".parse().unwrap();
+        assert_eq!(keymap.key("W"), Some(Key::Num5));
+        assert_eq!(keymap.key("Up"), Some(Key::Num5));
+        assert_eq!(keymap.key("Tab"), Some(Key::Exit));
+        assert_eq!(keymap.key("Escape"), None);
+
+        let keymap: Keymap = "keypad,0=Space".parse().unwrap();
+        assert_eq!(keymap.key("KPEnter"), Some(Key::E));
+        assert_eq!(keymap.key("Space"), Some(Key::Num0));
+        assert_eq!(keymap.key("KP0"), None);
+        assert_eq!(keymap.to_string().parse(), Ok(keymap));
+
+        assert_eq!(
+            "G=W".parse::<Keymap>(),
+            Err("invalid CHIP8 key `G`".to_string())
+        );
+        assert_eq!(
+            "1=Home".parse::<Keymap>(),
+            Err("unknown key `Home`".to_string())
+        );
+        assert_eq!(
+            "colemak".parse::<Keymap>(),
+            Err("unknown preset `colemak`".to_string())
+        );
+    }
+
+    #[test]
+    fn controller_profile() {
+        let keymap: Keymap = include_str!("../roms/PONG.keymap").parse().unwrap();
+        assert_eq!(keymap.key("W"), Some(Key::Num1));
+        assert_eq!(keymap.key("PadUp"), Some(Key::Num1));
+        assert_eq!(keymap.key("PadDown"), Some(Key::Num4));
+        assert_eq!(keymap.key("PadA"), Some(Key::Num5));
+        assert_eq!(keymap.key("Q"), None);
+
+        assert_eq!(key_name("pada"), Some("PadA"));
+        assert_eq!(scancode_name("PadA"), None);
+        assert_eq!(scancode_name("KPEnter"), Some("Keypad Enter"));
+        assert_eq!(button_name("PadL"), Some("leftshoulder"));
+    }
+
+    #[test]
+    fn layouts() {
+        assert_eq!(Layout::Qwerty.position('q'), Some("Q"));
+        assert_eq!(Layout::Qwerty.position('Q'), Some("Q"));
+        assert_eq!(Layout::Azerty.position('a'), Some("Q"));
+        assert_eq!(Layout::Azerty.position('&'), Some("1"));
+        assert_eq!(Layout::Azerty.position('1'), Some("1"));
+        assert_eq!(Layout::Azerty.position('w'), Some("Z"));
+        assert_eq!(Layout::Dvorak.position('\''), Some("Q"));
+        assert_eq!(Layout::Dvorak.position('o'), Some("S"));
+        assert_eq!(Layout::Dvorak.position('s'), Some("Semicolon"));
+        assert_eq!(Layout::Qwerty.position('~'), None);
+    }
+}
@@ -1,54 +1,237 @@
 pub mod args;
+pub mod asm;
+pub mod audio;
+pub mod backend;
+pub mod config;
 mod constants;
+#[cfg(feature = "sdl")]
+mod controller;
 mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
 mod keyboard;
+pub mod keymap;
+mod machine;
+pub mod movie;
+pub mod palette;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod romdb;
+pub mod scheduler;
+#[cfg(feature = "sdl")]
 mod screen;
+#[cfg(feature = "sdl")]
+mod speaker;
+#[cfg(feature = "tui")]
+mod terminal;
+mod vram;
 
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 
+pub use constants::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+pub use cpu::Cpu;
+pub use error::{Chip8Error, Result};
+pub use keyboard::{Hotkey, Key, KeyEvent, KeyState};
+pub use machine::Machine;
+pub use quirks::{Quirks, QuirksProfile};
+pub use scheduler::Speed;
+#[cfg(feature = "sdl")]
+pub use screen::WindowOptions;
+pub use vram::Vram;
+
 use std::fs;
-use std::time::Duration;
 
-// TODO: #[derive(Default)]
-pub struct Chip8 {
-    cpu: cpu::Cpu,
-    screen: screen::Screen,
-    keyboard: keyboard::Keyboard,
+use backend::{Audio, Display, Input};
+
+/// CHIP8 emulator running in a frontend: an SDL2 window or the terminal.
+pub struct Chip8<D, I, A> {
+    machine: Machine<D, I, A, backend::SystemClock>,
+    /// Movie file to record the session into
+    record: Option<String>,
+    /// Keymap before the overrides of the ROM
+    keymap: keymap::Keymap,
+    /// Whether the quirks and the speed were set, rather than taken from the
+    /// ROM database
+    custom_quirks: bool,
+    custom_speed: bool,
 }
 
-impl Chip8 {
-    pub fn new(scale: u8) -> Chip8 {
+#[cfg(feature = "sdl")]
+impl Chip8<screen::Screen, keyboard::Keyboard, speaker::SdlAudio> {
+    /// Open an SDL2 window, `scale` pixels wide per CHIP8 pixel.
+    pub fn new(scale: u8) -> Self {
+        Chip8::with_window(WindowOptions {
+            scale,
+            ..WindowOptions::default()
+        })
+    }
+
+    pub fn with_window(options: WindowOptions) -> Self {
         let sdl_context = sdl2::init().unwrap();
-        let screen = screen::Screen::new(&sdl_context, scale as usize);
+        let screen = screen::Screen::new(&sdl_context, options);
         let keyboard = keyboard::Keyboard::new(&sdl_context);
+        let speaker = speaker::SdlAudio::new(&sdl_context, audio::Tone::default());
 
         Chip8 {
-            cpu: cpu::Cpu::default(),
-            screen,
-            keyboard,
+            machine: Machine::new(screen, keyboard, speaker, backend::SystemClock::default()),
+            record: None,
+            keymap: keymap::Keymap::default(),
+            custom_quirks: false,
+            custom_speed: false,
         }
     }
 
-    pub fn run(&mut self, rom: &str) {
-        let rom: Vec<u8> = fs::read(rom).expect("No file found");
-        self.cpu.load_rom(&rom);
+    pub fn set_tone(&mut self, tone: audio::Tone) {
+        self.machine.audio_mut().set_tone(tone);
+    }
+}
 
-        'main: loop {
-            let pressed_keys = self.keyboard.pressed_keys();
+#[cfg(feature = "tui")]
+impl Chip8<terminal::TerminalScreen, terminal::TerminalKeyboard, terminal::Bell> {
+    /// Take over the terminal, restored when the emulator is dropped.
+    pub fn terminal() -> Result<Self> {
+        let screen = terminal::TerminalScreen::new()?;
+        let keyboard = terminal::TerminalKeyboard::new(screen.reports_releases());
 
-            for key in pressed_keys.iter() {
-                if let keyboard::Key::Exit = key {
-                    log::info!("Exit key pressed...");
-                    break 'main;
-                };
-            }
+        Ok(Chip8 {
+            machine: Machine::new(
+                screen,
+                keyboard,
+                terminal::Bell::default(),
+                backend::SystemClock::default(),
+            ),
+            record: None,
+            keymap: keymap::Keymap::default(),
+            custom_quirks: false,
+            custom_speed: false,
+        })
+    }
+
+    /// Keyboard layout, to find which keys typed the characters read.
+    pub fn set_layout(&mut self, layout: keymap::Layout) {
+        self.machine.input_mut().set_layout(layout);
+    }
+
+    /// Time a key stays held down without being repeated by the terminal,
+    /// unless it reports the releases.
+    pub fn set_release_delay(&mut self, delay: std::time::Duration) {
+        self.machine.input_mut().set_release_delay(delay);
+    }
+}
+
+impl<D: Display, I: Input, A: Audio> Chip8<D, I, A> {
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.machine.set_quirks(quirks);
+        self.custom_quirks = true;
+    }
+
+    pub fn set_palette(&mut self, palette: palette::Palette) {
+        self.machine.display_mut().set_palette(palette);
+    }
 
-            self.cpu.decrease_timers();
-            self.cpu.tick(pressed_keys);
-            if self.cpu.refresh_screen() {
-                self.screen.tick(self.cpu.vram());
+    pub fn set_keymap(&mut self, keymap: keymap::Keymap) {
+        self.machine.input_mut().set_keymap(&keymap);
+        self.keymap = keymap;
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.machine.set_speed(speed);
+        self.custom_speed = true;
+    }
+
+    /// Change the frames per second, keeping the instructions per second.
+    pub fn set_frame_rate(&mut self, frames_per_second: u32) {
+        let cycles_per_second = self.machine.speed().cycles_per_second();
+        self.machine.set_speed(Speed::from_cycles_per_second(
+            cycles_per_second,
+            frames_per_second,
+        ));
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.machine.set_seed(seed);
+    }
+
+    /// Record the session into a movie file, written when the emulation stops.
+    pub fn record(&mut self, path: &str) {
+        self.record = Some(path.to_string());
+    }
+
+    /// Play back a movie instead of reading the keyboard.
+    pub fn set_replay(&mut self, movie: movie::Movie) {
+        self.machine.set_replay(movie);
+        self.custom_quirks = true;
+        self.custom_speed = true;
+    }
+
+    pub fn set_rewind(&mut self, rewind: rewind::Rewind) {
+        self.machine.set_rewind(rewind);
+    }
+
+    /// Start paused in the debugger, reading commands from the standard input.
+    pub fn enable_debugger(&mut self) {
+        self.machine.set_debugger(debugger::Debugger::stdio());
+    }
+
+    /// Run the ROM at the given path.
+    ///
+    /// SUPER-CHIP RPL user flags are persisted next to the ROM, in a `.rpl` file.
+    /// Bindings in a `.keymap` file next to the ROM override the keymap.
+    ///
+    /// Unless set, the quirks and the speed are those of the ROM database.
+    pub fn run(&mut self, rom: &str) -> Result<()> {
+        let rpl_path = format!("{}.rpl", rom);
+        let keymap_path = format!("{}.keymap", rom);
+        if let Ok(spec) = fs::read_to_string(&keymap_path) {
+            let mut keymap = self.keymap.clone();
+            keymap
+                .apply(&spec)
+                .map_err(|message| Chip8Error::InvalidKeymap {
+                    file: keymap_path,
+                    message,
+                })?;
+            self.machine.input_mut().set_keymap(&keymap);
+        }
+        self.machine.set_save_path(rom);
+        let rom: Vec<u8> = fs::read(rom)?;
+        if let Some(metadata) = romdb::lookup(&rom) {
+            log::info!("{} ({})", metadata.title, metadata.platform);
+            if !self.custom_quirks {
+                self.machine.set_quirks(metadata.quirks.into());
+            }
+            if let (false, Some(tickrate)) = (self.custom_speed, metadata.tickrate) {
+                let fps = self.machine.speed().frames_per_second;
+                let cycles_per_second = tickrate * scheduler::TIMER_HZ;
+                self.machine
+                    .set_speed(Speed::from_cycles_per_second(cycles_per_second, fps));
             }
-            ::std::thread::sleep(Duration::from_millis(4));
         }
+        self.machine.load_rom(&rom)?;
+        log::info!("Random seed: {}", self.machine.cpu().seed());
+        if self.record.is_some() {
+            self.machine.record();
+        }
+
+        let mut saved_rpl = [0; 16];
+        if let Ok(bytes) = fs::read(&rpl_path) {
+            let len = bytes.len().min(saved_rpl.len());
+            saved_rpl[..len].copy_from_slice(&bytes[..len]);
+            self.machine.set_rpl_flags(saved_rpl);
+        }
+
+        let result = self.machine.run();
+
+        let rpl = self.machine.rpl_flags();
+        if rpl != saved_rpl {
+            fs::write(&rpl_path, rpl)?;
+        }
+        if let (Some(path), Some(movie)) = (&self.record, self.machine.take_movie()) {
+            movie.save(path)?;
+        }
+
+        result
     }
 }
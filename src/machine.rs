@@ -0,0 +1,561 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::backend::{Audio, Clock, Display, Input};
+use crate::cpu::Cpu;
+use crate::debugger::Debugger;
+use crate::error::Result;
+use crate::keyboard::{Hotkey, Key, KeyEvent, KeyState};
+use crate::movie::{Frame, Movie};
+use crate::quirks::Quirks;
+use crate::rewind::Rewind;
+use crate::scheduler::{Scheduler, Speed};
+use crate::vram::Vram;
+
+/// Movie being recorded or played back.
+enum MovieMode {
+    Recording(Movie),
+    /// Playing the movie, at the given frame
+    Replaying(Movie, usize),
+}
+
+/// CHIP8 machine independent of any frontend.
+///
+/// The display, input, audio and clock backends are injected, so the same
+/// machine runs in an SDL2 window, in a test or inside another application.
+pub struct Machine<D, I, A, C> {
+    cpu: Cpu,
+    display: D,
+    input: I,
+    audio: A,
+    clock: C,
+
+    scheduler: Scheduler,
+    /// Keys held down, updated at the beginning of each frame
+    keys: KeyState,
+    /// The VRAM changed since the last frame was drawn
+    vram_changed: bool,
+
+    debugger: Option<Debugger>,
+    /// Prefix of the quick-save slot files
+    save_path: Option<PathBuf>,
+    rewind: Option<Rewind>,
+    /// The rewind hotkey is held down
+    rewinding: bool,
+    movie: Option<MovieMode>,
+}
+
+impl<D, I, A, C> Machine<D, I, A, C>
+where
+    D: Display,
+    I: Input,
+    A: Audio,
+    C: Clock,
+{
+    pub fn new(display: D, input: I, audio: A, clock: C) -> Self {
+        Machine {
+            cpu: Cpu::default(),
+            display,
+            input,
+            audio,
+            clock,
+            scheduler: Scheduler::default(),
+            keys: KeyState::new(),
+            vram_changed: false,
+            debugger: None,
+            save_path: None,
+            rewind: None,
+            rewinding: false,
+            movie: None,
+        }
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<()> {
+        self.cpu.load_rom(rom)?;
+        self.scheduler.reset(self.clock.now());
+        Ok(())
+    }
+
+    /// Run under the given debugger, which starts paused.
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Enable the quick-save hotkeys, slot N being saved to `<path>.stateN`.
+    pub fn set_save_path(&mut self, path: impl Into<PathBuf>) {
+        self.save_path = Some(path.into());
+    }
+
+    /// Record the state at the end of every frame, enabling the rewind hotkey
+    /// and [`Machine::rewind_frame`].
+    pub fn set_rewind(&mut self, rewind: Rewind) {
+        self.rewind = Some(rewind);
+    }
+
+    pub fn rewind(&self) -> Option<&Rewind> {
+        self.rewind.as_ref()
+    }
+
+    /// Go back to the state at the end of the previous frame.
+    ///
+    /// Return `false` if rewinding is disabled or there is no older frame.
+    pub fn rewind_frame(&mut self) -> Result<bool> {
+        let Some(rewind) = self.rewind.as_mut() else {
+            return Ok(false);
+        };
+        if !rewind.step_back(&mut self.cpu)? {
+            return Ok(false);
+        }
+        self.vram_changed = true;
+        self.forward_audio_pattern();
+        Ok(true)
+    }
+
+    /// Start recording the input of every frame into a movie, with the current
+    /// seed, quirks and speed. Must be called before running the first frame.
+    pub fn record(&mut self) {
+        let movie = Movie::new(self.cpu.seed(), self.quirks(), self.speed());
+        self.movie = Some(MovieMode::Recording(movie));
+    }
+
+    /// Stop recording, returning the movie.
+    pub fn take_movie(&mut self) -> Option<Movie> {
+        match self.movie.take() {
+            Some(MovieMode::Recording(movie)) => Some(movie),
+            other => {
+                self.movie = other;
+                None
+            }
+        }
+    }
+
+    /// Play back the input of a movie instead of the one of the frontend,
+    /// stopping at its end. Must be called before running the first frame.
+    pub fn set_replay(&mut self, movie: Movie) {
+        self.set_seed(movie.seed);
+        self.set_quirks(movie.quirks);
+        self.set_speed(movie.speed);
+        self.movie = Some(MovieMode::Replaying(movie, 0));
+    }
+
+    /// Serialise the state of the CPU, see [`Cpu::snapshot`].
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.cpu.snapshot()
+    }
+
+    /// Restore a state saved by [`Machine::snapshot`].
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<()> {
+        self.cpu.restore(snapshot)?;
+        self.vram_changed = true;
+        self.forward_audio_pattern();
+        Ok(())
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.scheduler.speed()
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.scheduler.set_speed(speed);
+    }
+
+    /// Seed the random generator of CXKK, for reproducible runs.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.cpu.set_seed(seed);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.cpu.quirks()
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+    }
+
+    /// Execute a single instruction with the keys held down at the beginning of the frame.
+    ///
+    /// Timers are not decreased and the display is not refreshed, see [`Machine::frame`].
+    pub fn step(&mut self) -> Result<()> {
+        self.cpu.tick(&self.keys)?;
+        self.vram_changed |= self.cpu.refresh_screen();
+        self.forward_audio_pattern();
+        Ok(())
+    }
+
+    fn forward_audio_pattern(&mut self) {
+        if self.cpu.refresh_audio() {
+            if let Some(pattern) = self.cpu.audio_pattern() {
+                self.audio.set_pattern(pattern, self.cpu.pitch());
+            }
+        }
+    }
+
+    /// Run a frame: poll the input, execute the instructions of the frame, decrease
+    /// the timers at 60Hz, refresh the display and wait for the next frame.
+    ///
+    /// While the rewind hotkey is held, go back one frame instead of executing.
+    ///
+    /// Return `false` if the input or the program requested to exit.
+    pub fn frame(&mut self) -> Result<bool> {
+        for event in self.input.poll() {
+            match event {
+                KeyEvent::Pressed(Key::Exit) => {
+                    log::info!("Exit key pressed...");
+                    return Ok(false);
+                }
+                KeyEvent::Hotkey(hotkey) => self.hotkey(hotkey),
+                // The keys come from the movie
+                _ if matches!(self.movie, Some(MovieMode::Replaying(..))) => (),
+                _ => self.keys.apply(event),
+            }
+        }
+
+        match self.movie.as_mut() {
+            Some(MovieMode::Replaying(movie, frame)) => match movie.frames().get(*frame) {
+                Some(frame) => self.keys = frame.keys,
+                None => {
+                    log::info!("Replay finished...");
+                    return Ok(false);
+                }
+            },
+            // The timer ticks are known at the end of the frame
+            Some(MovieMode::Recording(movie)) => movie.push(Frame {
+                keys: self.keys,
+                timer_ticks: 0,
+            }),
+            None => (),
+        }
+
+        if self.rewinding {
+            self.rewind_frame()?;
+            self.audio.set_playing(false);
+        } else {
+            if !self.run_frame()? {
+                return Ok(false);
+            }
+            self.audio.set_playing(self.cpu.sound_timer() > 0);
+            if let Some(rewind) = self.rewind.as_mut() {
+                rewind.capture(&self.cpu);
+            }
+        }
+        self.audio.advance(self.scheduler.speed().frame_duration());
+
+        if self.vram_changed || self.display.needs_redraw() {
+            self.display.draw(self.cpu.vram());
+            self.vram_changed = false;
+        }
+
+        let delay = self.scheduler.frame_delay(self.clock.now());
+        self.clock.sleep(delay);
+
+        Ok(true)
+    }
+
+    /// Execute the instructions of the frame and decrease the timers.
+    /// Return `false` if the program or the debugger requested to exit.
+    fn run_frame(&mut self) -> Result<bool> {
+        for _ in 0..self.scheduler.speed().instructions_per_frame {
+            if let Some(debugger) = self.debugger.as_mut() {
+                // Show the screen as it is when stopping
+                if self.vram_changed {
+                    self.display.draw(self.cpu.vram());
+                    self.vram_changed = false;
+                }
+                if !debugger.before_step(&self.cpu)? {
+                    log::info!("Debugger quit...");
+                    return Ok(false);
+                }
+            }
+            self.step()?;
+            if let Some(debugger) = self.debugger.as_mut() {
+                debugger.after_step(&self.cpu);
+            }
+            if self.cpu.halted() {
+                log::info!("Program exited...");
+                return Ok(false);
+            }
+        }
+
+        let timer_ticks = match self.movie.as_mut() {
+            Some(MovieMode::Replaying(movie, frame)) => {
+                *frame += 1;
+                movie.frames()[*frame - 1].timer_ticks
+            }
+            _ => self.scheduler.timer_ticks(self.clock.now()),
+        };
+        if let Some(MovieMode::Recording(movie)) = self.movie.as_mut() {
+            movie.set_last_timer_ticks(timer_ticks);
+        }
+        for _ in 0..timer_ticks {
+            self.cpu.decrease_timers();
+        }
+
+        Ok(true)
+    }
+
+    /// Run frames until the input or the program requests to exit.
+    pub fn run(&mut self) -> Result<()> {
+        while self.frame()? {}
+        Ok(())
+    }
+
+    fn hotkey(&mut self, hotkey: Hotkey) {
+        let rewrites_history = matches!(hotkey, Hotkey::LoadState(_) | Hotkey::Rewind(true));
+        if rewrites_history && self.movie.is_some() {
+            log::warn!("Loading states and rewinding are disabled with movies");
+            return;
+        }
+
+        match hotkey {
+            Hotkey::SaveState(slot) => self.save_state(slot),
+            Hotkey::LoadState(slot) => self.load_state(slot),
+            Hotkey::Rewind(rewinding) => self.rewinding = rewinding,
+            Hotkey::Fullscreen => self.display.toggle_fullscreen(),
+        }
+    }
+
+    fn state_path(&self, slot: u8) -> Option<PathBuf> {
+        let mut path = OsString::from(self.save_path.as_ref()?);
+        path.push(format!(".state{}", slot));
+        Some(path.into())
+    }
+
+    /// Failing to save or load a state is reported without stopping the game.
+    fn save_state(&mut self, slot: u8) {
+        let Some(path) = self.state_path(slot) else {
+            log::warn!("No save path, cannot save state {}", slot);
+            return;
+        };
+        match fs::write(&path, self.snapshot()) {
+            Ok(()) => log::info!("State saved to {}", path.display()),
+            Err(err) => log::error!("Cannot save state to {}: {}", path.display(), err),
+        }
+    }
+
+    fn load_state(&mut self, slot: u8) {
+        let Some(path) = self.state_path(slot) else {
+            log::warn!("No save path, cannot load state {}", slot);
+            return;
+        };
+        let result = fs::read(&path)
+            .map_err(|err| err.into())
+            .and_then(|snapshot| self.restore(&snapshot));
+        match result {
+            Ok(()) => log::info!("State loaded from {}", path.display()),
+            Err(err) => log::error!("Cannot load state from {}: {}", path.display(), err),
+        }
+    }
+
+    pub fn vram(&self) -> &Vram {
+        self.cpu.vram()
+    }
+
+    /// SUPER-CHIP RPL user flags, to be persisted across runs.
+    pub fn rpl_flags(&self) -> [u8; 16] {
+        self.cpu.rpl_flags()
+    }
+
+    pub fn set_rpl_flags(&mut self, rpl: [u8; 16]) {
+        self.cpu.set_rpl_flags(rpl);
+    }
+
+    pub fn display(&self) -> &D {
+        &self.display
+    }
+
+    pub fn display_mut(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    pub fn audio(&self) -> &A {
+        &self.audio
+    }
+
+    pub fn audio_mut(&mut self) -> &mut A {
+        &mut self.audio
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{BufferAudio, Tone};
+    use crate::backend::{NullAudio, NullClock, NullDisplay, NullInput};
+    use std::collections::VecDeque;
+
+    /// Input returning the given events, one list per frame.
+    struct ScriptedInput(VecDeque<Vec<KeyEvent>>);
+
+    impl Input for ScriptedInput {
+        fn poll(&mut self) -> Vec<KeyEvent> {
+            self.0.pop_front().unwrap_or_default()
+        }
+    }
+
+    #[test]
+    fn runs_without_frontend() {
+        let mut machine = Machine::new(NullDisplay, NullInput, NullAudio, NullClock::default());
+        // LD V0, 0; LD F, V0; DRW V0, V0, 5
+        machine
+            .load_rom(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05])
+            .unwrap();
+
+        for _ in 0..3 {
+            machine.step().unwrap();
+        }
+
+        // Top row of the "0" glyph: 0xF0
+        let row: Vec<bool> = (0..8).map(|x| machine.vram().get(x, 0)).collect();
+        assert_eq!(
+            row,
+            vec![true, true, true, true, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_opcode() {
+        let mut machine = Machine::new(NullDisplay, NullInput, NullAudio, NullClock::default());
+        machine.load_rom(&[0xFF, 0xFF]).unwrap();
+
+        let err = machine.run().unwrap_err();
+
+        assert_eq!(err.to_string(), "unknown opcode FFFF at 0x200");
+    }
+
+    #[test]
+    fn beeps_while_sound_timer_active() {
+        let audio = BufferAudio::new(Tone::default(), 6000);
+        let mut machine = Machine::new(NullDisplay, NullInput, audio, NullClock::default());
+        machine.set_speed(Speed::new(10, 60));
+        // LD V0, 2; LD ST, V0; JP 0x204
+        machine
+            .load_rom(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04])
+            .unwrap();
+
+        for _ in 0..4 {
+            assert!(machine.frame().unwrap());
+        }
+
+        // 100 samples per frame, the sound timer expires at the end of the 3rd frame
+        let samples = machine.audio().samples();
+        assert_eq!(samples.len(), 400);
+        assert!(samples[..200].iter().all(|sample| *sample != 0.0));
+        assert!(samples[200..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn timers_run_at_60hz() {
+        let mut machine = Machine::new(NullDisplay, NullInput, NullAudio, NullClock::default());
+        machine.set_speed(Speed::new(10, 60));
+        // LD V0, 60; LD ST, V0; JP 0x204
+        machine
+            .load_rom(&[0x60, 0x3C, 0xF0, 0x18, 0x12, 0x04])
+            .unwrap();
+
+        // Timers are updated at the end of each frame: at the end of the 31st one, half a
+        // second has elapsed since the first
+        for _ in 0..31 {
+            assert!(machine.frame().unwrap());
+        }
+
+        assert_eq!(machine.cpu.sound_timer(), 30);
+    }
+
+    #[test]
+    fn quick_save_slots() {
+        let script = vec![
+            vec![],
+            vec![KeyEvent::Hotkey(Hotkey::SaveState(1))],
+            vec![],
+            vec![KeyEvent::Hotkey(Hotkey::LoadState(1))],
+        ];
+        let input = ScriptedInput(script.into());
+        let mut machine = Machine::new(NullDisplay, input, NullAudio, NullClock::default());
+        let path = std::env::temp_dir().join(format!("chip8-machine-{}", std::process::id()));
+        machine.set_save_path(&path);
+        machine.set_speed(Speed::new(2, 60));
+        // ADD V0, 1; JP 0x200
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        for _ in 0..3 {
+            machine.frame().unwrap();
+        }
+        assert_eq!(machine.cpu.v()[0], 3);
+        machine.frame().unwrap();
+        fs::remove_file(machine.state_path(1).unwrap()).unwrap();
+
+        // Restored to 1 before running the last frame
+        assert_eq!(machine.cpu.v()[0], 2);
+    }
+
+    #[test]
+    fn rewind_while_hotkey_held() {
+        let script = vec![
+            vec![],
+            vec![],
+            vec![],
+            vec![KeyEvent::Hotkey(Hotkey::Rewind(true))],
+            vec![],
+            vec![KeyEvent::Hotkey(Hotkey::Rewind(false))],
+        ];
+        let input = ScriptedInput(script.into());
+        let mut machine = Machine::new(NullDisplay, input, NullAudio, NullClock::default());
+        machine.set_rewind(Rewind::default());
+        machine.set_speed(Speed::new(2, 60));
+        // ADD V0, 1; JP 0x200
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        for _ in 0..3 {
+            machine.frame().unwrap();
+        }
+        assert_eq!(machine.cpu.v()[0], 3);
+
+        machine.frame().unwrap();
+        machine.frame().unwrap();
+        assert_eq!(machine.cpu.v()[0], 1, "Two frames rewound");
+
+        machine.frame().unwrap();
+        assert_eq!(machine.cpu.v()[0], 2, "Running again");
+        assert!(machine.rewind_frame().unwrap());
+        assert_eq!(machine.cpu.v()[0], 1);
+    }
+
+    #[test]
+    fn replay_recorded_movie() {
+        let script = vec![
+            vec![],
+            vec![KeyEvent::Pressed(Key::Num5)],
+            vec![],
+            vec![],
+            vec![KeyEvent::Released(Key::Num5)],
+        ];
+        let input = ScriptedInput(script.into());
+        let mut machine = Machine::new(NullDisplay, input, NullAudio, NullClock::default());
+        machine.set_speed(Speed::new(5, 60));
+        // RND V0, 0x3F; LD V1, 5; SKNP V1; DRW V0, V2, 1; JP 0x200
+        let rom = [0xC0, 0x3F, 0x61, 0x05, 0xE1, 0xA1, 0xD0, 0x21, 0x12, 0x00];
+        machine.load_rom(&rom).unwrap();
+        machine.record();
+
+        for _ in 0..8 {
+            machine.frame().unwrap();
+        }
+        let movie = machine.take_movie().unwrap();
+        assert_eq!(movie.frames().len(), 8);
+        assert_eq!(movie.frames()[2].keys.bits(), 1 << 5);
+
+        let vram = crate::movie::replay(&rom, &movie).unwrap();
+
+        assert!(vram.pixels().any(|on| on));
+        assert!(vram.pixels().eq(machine.vram().pixels()));
+    }
+}
@@ -1,11 +1,113 @@
-use chip8::args::Args;
-use chip8::Chip8;
-use clap::Parser;
+use chip8::args::{Args, Command, Frontend};
+use chip8::backend::{Audio, Display, Input};
+use chip8::movie::{self, Movie};
+use chip8::{Chip8, Vram};
+use clap::error::ErrorKind;
+use clap::CommandFactory;
 
 pub fn main() {
     env_logger::init();
 
-    let args = Args::parse();
-    let mut chip8 = Chip8::new(args.scale);
-    chip8.run(&args.rom);
+    let args = match Args::load() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if args.debug && args.frontend == Frontend::Tui {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--debug reads commands from the terminal, it requires --frontend sdl",
+            )
+            .exit();
+    }
+    if let Err(err) = run(args) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Args) -> chip8::Result<()> {
+    match &args.command {
+        Some(Command::Disasm { rom }) => {
+            let rom = std::fs::read(rom)?;
+            print!("{}", chip8::disasm::disassemble(&rom)?);
+            Ok(())
+        }
+        Some(Command::Info { rom }) => {
+            let rom = std::fs::read(rom)?;
+            print!("{}", chip8::romdb::info(&rom));
+            Ok(())
+        }
+        Some(Command::Asm { source, output }) => {
+            let rom = chip8::asm::assemble_file(source)?;
+            let output = match output {
+                Some(output) => output.into(),
+                None => std::path::Path::new(source).with_extension("ch8"),
+            };
+            std::fs::write(output, rom)?;
+            Ok(())
+        }
+        None if args.headless => {
+            let movie = Movie::load(args.replay.as_deref().unwrap_or_default())?;
+            let rom = std::fs::read(args.rom.as_deref().unwrap_or_default())?;
+            print_screen(&movie::replay(&rom, &movie)?);
+            Ok(())
+        }
+        None => match args.frontend {
+            Frontend::Sdl => {
+                let mut chip8 = Chip8::with_window(args.window());
+                chip8.set_tone(args.tone());
+                play(chip8, &args)
+            }
+            Frontend::Tui => {
+                let mut chip8 = Chip8::terminal()?;
+                chip8.set_layout(args.layout);
+                chip8.set_release_delay(std::time::Duration::from_millis(args.release_delay));
+                play(chip8, &args)
+            }
+        },
+    }
+}
+
+fn play<D: Display, I: Input, A: Audio>(
+    mut chip8: Chip8<D, I, A>,
+    args: &Args,
+) -> chip8::Result<()> {
+    if let Some(quirks) = args.quirks {
+        chip8.set_quirks(quirks.into());
+    }
+    chip8.set_palette(args.palette());
+    chip8.set_keymap(args.keymap.clone());
+    match args.speed() {
+        Some(speed) => chip8.set_speed(speed),
+        None => chip8.set_frame_rate(args.fps),
+    }
+    if let Some(seed) = args.seed {
+        chip8.set_seed(seed);
+    }
+    if let Some(rewind) = args.rewind() {
+        chip8.set_rewind(rewind);
+    }
+    if let Some(path) = &args.record {
+        chip8.record(path);
+    }
+    if let Some(path) = &args.replay {
+        chip8.set_replay(Movie::load(path)?);
+    }
+    if args.debug {
+        chip8.enable_debugger();
+    }
+    chip8.run(args.rom.as_deref().unwrap_or_default())
+}
+
+fn print_screen(vram: &Vram) {
+    for y in 0..vram.height() {
+        let row: String = (0..vram.width())
+            .map(|x| if vram.get(x, y) { '#' } else { '.' })
+            .collect();
+        println!("{}", row);
+    }
 }
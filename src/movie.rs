@@ -0,0 +1,248 @@
+//! Movie files: the input of every frame, to replay a session exactly.
+//!
+//! A movie records what the emulation depends on besides the ROM: the random
+//! seed, the quirks, the speed and, for each frame, the keys held down and the
+//! number of 60Hz timer ticks. It is a text file, runs of identical frames
+//! being written once with a `*count` suffix:
+//!
+//! ```text
+//! chip8-movie 1
+//! seed 1234
+//! quirks load_store
+//! speed 10 60
+//! frames
+//! 0000 1 *120
+//! 0020 1 *3
+//! 0000 2
+//! ```
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::backend::{NullAudio, NullClock, NullDisplay, NullInput};
+use crate::error::{Chip8Error, Result};
+use crate::keyboard::KeyState;
+use crate::machine::Machine;
+use crate::quirks::Quirks;
+use crate::scheduler::Speed;
+use crate::vram::Vram;
+
+const HEADER: &str = "chip8-movie 1";
+/// Longest movie read, a day at 60 frames per second
+const MAX_FRAMES: usize = 60 * 60 * 60 * 24;
+/// Most timer ticks in a frame, the timers being stopped after 255
+const MAX_TIMER_TICKS: u32 = 255;
+
+/// Input of a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Keys held down while executing the instructions of the frame
+    pub keys: KeyState,
+    /// Timer ticks at the end of the frame
+    pub timer_ticks: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub speed: Speed,
+    frames: Vec<Frame>,
+}
+
+impl Movie {
+    pub fn new(seed: u64, quirks: Quirks, speed: Speed) -> Movie {
+        Movie {
+            seed,
+            quirks,
+            speed,
+            frames: vec![],
+        }
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+
+    /// Complete the frame being recorded once its timers have ticked.
+    pub(crate) fn set_last_timer_ticks(&mut self, timer_ticks: u32) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.timer_ticks = timer_ticks;
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        writeln!(writer, "seed {}", self.seed)?;
+        writeln!(writer, "quirks {}", self.quirks)?;
+        writeln!(
+            writer,
+            "speed {} {}",
+            self.speed.instructions_per_frame, self.speed.frames_per_second
+        )?;
+        writeln!(writer, "frames")?;
+
+        for run in self.frames.chunk_by(|a, b| a == b) {
+            let frame = run[0];
+            write!(writer, "{:04X} {}", frame.keys.bits(), frame.timer_ticks)?;
+            if run.len() > 1 {
+                write!(writer, " *{}", run.len())?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Movie> {
+        let mut movie = Movie::new(0, Quirks::default(), Speed::default());
+        let mut in_frames = false;
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let invalid = |message: String| Chip8Error::InvalidMovie {
+                line: number + 1,
+                message,
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            if number == 0 {
+                if line.trim() != HEADER {
+                    return Err(invalid("not a movie file".to_string()));
+                }
+                continue;
+            }
+            if words.is_empty() {
+                continue;
+            }
+
+            if in_frames {
+                let (keys, ticks, count) = match words[..] {
+                    [keys, ticks] => (keys, ticks, "*1"),
+                    [keys, ticks, count] => (keys, ticks, count),
+                    _ => return Err(invalid(format!("invalid frame `{}`", line))),
+                };
+                let frame = Frame {
+                    keys: u16::from_str_radix(keys, 16)
+                        .map_err(|_| invalid(format!("invalid keys `{}`", keys)))?
+                        .into(),
+                    timer_ticks: ticks
+                        .parse()
+                        .ok()
+                        .filter(|&ticks| ticks <= MAX_TIMER_TICKS)
+                        .ok_or_else(|| invalid(format!("invalid timer ticks `{}`", ticks)))?,
+                };
+                let count: usize = count
+                    .strip_prefix('*')
+                    .and_then(|count| count.parse().ok())
+                    .ok_or_else(|| invalid(format!("invalid count `{}`", count)))?;
+                movie
+                    .frames
+                    .len()
+                    .checked_add(count)
+                    .filter(|&total| total <= MAX_FRAMES)
+                    .ok_or_else(|| invalid(format!("more than {} frames", MAX_FRAMES)))?;
+                movie.frames.extend(std::iter::repeat_n(frame, count));
+                continue;
+            }
+
+            match words[..] {
+                ["seed", seed] => {
+                    movie.seed = seed
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid seed `{}`", seed)))?;
+                }
+                ["quirks", quirks] => movie.quirks = quirks.parse().map_err(invalid)?,
+                ["speed", ipf, fps] => match (ipf.parse(), fps.parse()) {
+                    (Ok(ipf), Ok(fps)) => movie.speed = Speed::new(ipf, fps),
+                    _ => return Err(invalid(format!("invalid speed `{} {}`", ipf, fps))),
+                },
+                ["frames"] => in_frames = true,
+                _ => return Err(invalid(format!("unexpected `{}`", line))),
+            }
+        }
+
+        Ok(movie)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Movie> {
+        Movie::read(io::BufReader::new(fs::File::open(path)?))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Play the movie without any frontend, as fast as possible, returning the
+/// screen at the end.
+pub fn replay(rom: &[u8], movie: &Movie) -> Result<Vram> {
+    let mut machine = Machine::new(NullDisplay, NullInput, NullAudio, NullClock::default());
+    machine.load_rom(rom)?;
+    machine.set_replay(movie.clone());
+    while machine.frame()? {}
+    Ok(machine.vram().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read() {
+        let mut movie = Movie::new(42, Quirks::SUPER_CHIP, Speed::new(15, 60));
+        let idle = Frame {
+            keys: KeyState::new(),
+            timer_ticks: 1,
+        };
+        let pressed = Frame {
+            keys: 0x0020.into(),
+            timer_ticks: 2,
+        };
+        for frame in [idle, idle, idle, pressed, idle] {
+            movie.push(frame);
+        }
+
+        let mut file = vec![];
+        movie.write(&mut file).unwrap();
+        let text = String::from_utf8(file.clone()).unwrap();
+
+        assert_eq!(
+            text,
+            "chip8-movie 1\nseed 42\nquirks shift,jump,clipping\nspeed 15 60\nframes\n\
+             0000 1 *3\n0020 2\n0000 1\n"
+        );
+        assert_eq!(Movie::read(&file[..]).unwrap(), movie);
+    }
+
+    #[test]
+    fn invalid_movie() {
+        let err = Movie::read("chip8-movie 1\nseed 1\nframes\n0000 x\n".as_bytes()).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "invalid movie at line 4: invalid timer ticks `x`"
+        );
+
+        let err =
+            Movie::read("chip8-movie 1\nframes\n0000 1 *99999999999999\n".as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid movie at line 3: more than 5184000 frames"
+        );
+
+        let err = Movie::read("chip8-movie 1\nframes\n0000 4294967295\n".as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid movie at line 3: invalid timer ticks `4294967295`"
+        );
+    }
+}
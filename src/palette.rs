@@ -0,0 +1,168 @@
+//! Colours of the screen, shared by every frontend.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// 24-bit RGB colour, written `#RRGGBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parse `#RRGGBB`, the `#` being optional.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        let invalid = || format!("invalid colour `{}`, expected #RRGGBB", s);
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Color::rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+/// Colour of each combination of XO-CHIP planes, indexed by `Vram::color`:
+/// the background, the first plane (the foreground of CHIP8 and SUPER-CHIP
+/// programs), the second plane and both planes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette(pub [Color; 4]);
+
+impl Palette {
+    pub fn background(&self) -> Color {
+        self.0[0]
+    }
+
+    pub fn foreground(&self) -> Color {
+        self.0[1]
+    }
+
+    /// Colour of a pixel of the given `Vram::color`.
+    pub fn color(&self, index: u8) -> Color {
+        self.0[index as usize]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Theme::default().palette()
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let colors: Vec<String> = self.0.iter().map(Color::to_string).collect();
+        write!(f, "{}", colors.join(","))
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    /// Parse comma-separated colours: the background and the foreground,
+    /// optionally followed by the colours of the second plane and of both
+    /// planes, which default to those of the classic theme.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colors = s
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Color>, _>>()?;
+        let mut palette = Theme::Classic.palette();
+        match colors.len() {
+            2 | 4 => palette.0[..colors.len()].copy_from_slice(&colors),
+            len => return Err(format!("expected 2 or 4 colours, got {}", len)),
+        }
+        Ok(palette)
+    }
+}
+
+/// Built-in palettes.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Theme {
+    /// White on black
+    #[default]
+    Classic,
+    /// Shades of green of an old handheld LCD
+    Lcd,
+    /// Amber monochrome monitor
+    Amber,
+    /// Yellow on brown, as in the Octo IDE
+    Octo,
+}
+
+impl Theme {
+    pub fn palette(&self) -> Palette {
+        let colors = match self {
+            Theme::Classic => [
+                Color::rgb(0x00, 0x00, 0x00),
+                Color::rgb(0xFF, 0xFF, 0xFF),
+                Color::rgb(0xAA, 0xAA, 0xAA),
+                Color::rgb(0x55, 0x55, 0x55),
+            ],
+            Theme::Lcd => [
+                Color::rgb(0x9B, 0xBC, 0x0F),
+                Color::rgb(0x0F, 0x38, 0x0F),
+                Color::rgb(0x8B, 0xAC, 0x0F),
+                Color::rgb(0x30, 0x62, 0x30),
+            ],
+            Theme::Amber => [
+                Color::rgb(0x1A, 0x0F, 0x00),
+                Color::rgb(0xFF, 0xB0, 0x00),
+                Color::rgb(0x99, 0x5C, 0x00),
+                Color::rgb(0xFF, 0xD8, 0x80),
+            ],
+            Theme::Octo => [
+                Color::rgb(0x99, 0x66, 0x00),
+                Color::rgb(0xFF, 0xCC, 0x00),
+                Color::rgb(0xFF, 0x66, 0x00),
+                Color::rgb(0x66, 0x22, 0x00),
+            ],
+        };
+        Palette(colors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!("#FFcc00".parse(), Ok(Color::rgb(0xFF, 0xCC, 0x00)));
+        assert_eq!("0f380f".parse(), Ok(Color::rgb(0x0F, 0x38, 0x0F)));
+        assert!("#FFF".parse::<Color>().is_err());
+        assert!("#GG0000".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn parse_palette() {
+        let palette: Palette = "#000080,#FFFFFF".parse().unwrap();
+        assert_eq!(palette.background(), Color::rgb(0, 0, 0x80));
+        assert_eq!(palette.foreground(), Color::rgb(0xFF, 0xFF, 0xFF));
+        assert_eq!(palette.color(3), Theme::Classic.palette().color(3));
+
+        let octo = Theme::Octo.palette();
+        assert_eq!(octo.to_string().parse(), Ok(octo));
+
+        assert_eq!(
+            "#000000,#FFFFFF,#AAAAAA".parse::<Palette>(),
+            Err("expected 2 or 4 colours, got 3".to_string())
+        );
+    }
+}
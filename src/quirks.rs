@@ -0,0 +1,176 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Behaviour of the instructions interpreted differently across CHIP8 implementations.
+///
+/// See https://github.com/Timendus/chip8-test-suite#quirks-test for the details of each one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VX in place, ignoring VY.
+    pub shift: bool,
+    /// FX55/FX65 increment I by X + 1.
+    pub load_store: bool,
+    /// BNNN jumps to NNN + VX, X being the highest nibble of NNN, instead of NNN + V0.
+    pub jump: bool,
+    /// DXYN clips sprites at the edges of the screen instead of wrapping them around.
+    pub clipping: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    /// DXYN waits for the next 60Hz tick before drawing.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// Original COSMAC VIP interpreter.
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift: false,
+        load_store: true,
+        jump: false,
+        clipping: true,
+        vf_reset: true,
+        display_wait: true,
+    };
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub const CHIP_48: Quirks = Quirks {
+        shift: true,
+        load_store: true,
+        jump: true,
+        clipping: true,
+        vf_reset: false,
+        display_wait: false,
+    };
+
+    /// SUPER-CHIP 1.1.
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift: true,
+        load_store: false,
+        jump: true,
+        clipping: true,
+        vf_reset: false,
+        display_wait: false,
+    };
+
+    /// Modern interpreters such as Octo.
+    pub const MODERN: Quirks = Quirks {
+        shift: false,
+        load_store: true,
+        jump: false,
+        clipping: false,
+        vf_reset: false,
+        display_wait: false,
+    };
+}
+
+/// Name of each quirk, in the order of the bits of [`Quirks::bits`]
+const NAMES: [&str; 6] = [
+    "shift",
+    "load_store",
+    "jump",
+    "clipping",
+    "vf_reset",
+    "display_wait",
+];
+
+impl Quirks {
+    /// One bit per quirk, for the binary formats.
+    pub fn bits(&self) -> u8 {
+        [
+            self.shift,
+            self.load_store,
+            self.jump,
+            self.clipping,
+            self.vf_reset,
+            self.display_wait,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (bit, enabled)| bits | (*enabled as u8) << bit)
+    }
+
+    pub fn from_bits(bits: u8) -> Quirks {
+        let enabled = |bit: usize| bits & (1 << bit) != 0;
+        Quirks {
+            shift: enabled(0),
+            load_store: enabled(1),
+            jump: enabled(2),
+            clipping: enabled(3),
+            vf_reset: enabled(4),
+            display_wait: enabled(5),
+        }
+    }
+}
+
+/// Comma-separated names of the enabled quirks, or `none`.
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bits = self.bits();
+        let names: Vec<&str> = (0..NAMES.len())
+            .filter(|bit| bits & (1 << bit) != 0)
+            .map(|bit| NAMES[bit])
+            .collect();
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(","))
+        }
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bits = 0;
+        for name in s.split(',').map(str::trim).filter(|name| *name != "none") {
+            match NAMES.iter().position(|known| *known == name) {
+                Some(bit) => bits |= 1 << bit,
+                None => return Err(format!("unknown quirk `{}`", name)),
+            }
+        }
+        Ok(Quirks::from_bits(bits))
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::MODERN
+    }
+}
+
+/// Named quirks presets.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuirksProfile {
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    #[default]
+    Modern,
+}
+
+impl From<QuirksProfile> for Quirks {
+    fn from(profile: QuirksProfile) -> Quirks {
+        match profile {
+            QuirksProfile::CosmacVip => Quirks::COSMAC_VIP,
+            QuirksProfile::Chip48 => Quirks::CHIP_48,
+            QuirksProfile::SuperChip => Quirks::SUPER_CHIP,
+            QuirksProfile::Modern => Quirks::MODERN,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(Quirks::MODERN.to_string(), "load_store");
+        assert_eq!(Quirks::from_bits(0).to_string(), "none");
+        for quirks in [Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::SUPER_CHIP] {
+            assert_eq!(quirks.to_string().parse(), Ok(quirks));
+            assert_eq!(Quirks::from_bits(quirks.bits()), quirks);
+        }
+        assert!("shift,wrap".parse::<Quirks>().is_err());
+    }
+}
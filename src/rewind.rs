@@ -0,0 +1,257 @@
+//! Rewind buffer keeping the states of the last frames, to step gameplay backwards.
+//!
+//! Each frame is stored as its difference with the following one: the two
+//! [snapshots](Cpu::snapshot) XORed together and run-length encoded, so a frame
+//! changing a few bytes of RAM and VRAM only takes a few bytes. Snapshots only
+//! hold the RAM in use, usually the first 4K, so capturing a frame copies and
+//! compares around 12K.
+
+use std::collections::VecDeque;
+
+use crate::cpu::Cpu;
+use crate::error::Result;
+
+/// Ring buffer of compressed per-frame CPU states.
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    /// Differences between consecutive states, the most recent last
+    deltas: VecDeque<Vec<u8>>,
+    /// Latest state captured, the deltas leading back from it
+    current: Option<Vec<u8>>,
+    depth: usize,
+    memory_limit: usize,
+    /// Bytes used by the deltas
+    memory: usize,
+}
+
+impl Rewind {
+    /// Keep up to `depth` frames, with their deltas taking at most `memory_limit` bytes.
+    pub fn new(depth: usize, memory_limit: usize) -> Rewind {
+        Rewind {
+            deltas: VecDeque::new(),
+            current: None,
+            depth,
+            memory_limit,
+            memory: 0,
+        }
+    }
+
+    /// Number of frames it is possible to step back.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Bytes used by the stored frames, besides the latest full state.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+        self.current = None;
+        self.memory = 0;
+    }
+
+    /// Record the state at the end of a frame, dropping the oldest frames
+    /// beyond the depth or memory limit.
+    pub fn capture(&mut self, cpu: &Cpu) {
+        if self.depth == 0 {
+            return;
+        }
+
+        let mut state = cpu.snapshot();
+        if let Some(mut current) = self.current.take() {
+            // The RAM comes last, the bytes after the part in use being zero
+            let len = current.len().max(state.len());
+            current.resize(len, 0);
+            state.resize(len, 0);
+            let delta = compress(&current, &state);
+            self.memory += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.current = Some(state);
+
+        while self.deltas.len() > self.depth || self.memory > self.memory_limit {
+            match self.deltas.pop_front() {
+                Some(delta) => self.memory -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Restore the CPU to the frame before the latest one captured.
+    ///
+    /// Return `false`, leaving the CPU untouched, if there is no older frame.
+    pub fn step_back(&mut self, cpu: &mut Cpu) -> Result<bool> {
+        let (Some(current), Some(delta)) = (self.current.as_mut(), self.deltas.pop_back()) else {
+            return Ok(false);
+        };
+        self.memory -= delta.len();
+        apply(current, &delta);
+        cpu.restore(current)?;
+        Ok(true)
+    }
+}
+
+impl Default for Rewind {
+    /// 10 seconds at 60 frames per second, in up to 64MiB.
+    fn default() -> Rewind {
+        Rewind::new(600, 64 << 20)
+    }
+}
+
+/// Encode the XOR of `old` and `new`, of the same length, as a sequence of
+/// unchanged bytes counts followed by changed bytes counts and their XOR.
+fn compress(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+
+    while i < new.len() {
+        let unchanged = i;
+        while i < new.len() && old[i] == new[i] {
+            i += 1;
+        }
+        let changed = i;
+        while i < new.len() && old[i] != new[i] {
+            i += 1;
+        }
+
+        write_varint(&mut out, changed - unchanged);
+        write_varint(&mut out, i - changed);
+        out.extend(
+            old[changed..i]
+                .iter()
+                .zip(&new[changed..i])
+                .map(|(a, b)| a ^ b),
+        );
+    }
+
+    out
+}
+
+/// XOR the delta into `state`, turning either state it was computed from into the other.
+fn apply(state: &mut [u8], delta: &[u8]) {
+    let mut delta = delta;
+    let mut i = 0;
+
+    while !delta.is_empty() {
+        i += read_varint(&mut delta);
+        let len = read_varint(&mut delta);
+        for (byte, xor) in state[i..i + len].iter_mut().zip(&delta[..len]) {
+            *byte ^= xor;
+        }
+        delta = &delta[len..];
+        i += len;
+    }
+}
+
+/// LEB128 encoding, 7 bits per byte.
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> usize {
+    let mut value = 0;
+    for (i, byte) in input.iter().enumerate() {
+        value |= ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            *input = &input[i + 1..];
+            break;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::KeyState;
+
+    #[test]
+    fn delta_round_trip() {
+        let old = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let new = [0, 1, 9, 9, 4, 5, 6, 7, 8, 0];
+
+        let delta = compress(&old, &new);
+        assert_eq!(delta, [2, 2, 2 ^ 9, 3 ^ 9, 5, 1, 9]);
+
+        let mut state = new;
+        apply(&mut state, &delta);
+        assert_eq!(state, old);
+        apply(&mut state, &delta);
+        assert_eq!(state, new);
+    }
+
+    #[test]
+    fn step_back_frame_by_frame() {
+        let mut cpu = Cpu::default();
+        // ADD V0, 1; JP 0x200
+        cpu.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut rewind = Rewind::new(3, usize::MAX);
+
+        for _ in 0..5 {
+            cpu.tick(&KeyState::new()).unwrap();
+            cpu.tick(&KeyState::new()).unwrap();
+            rewind.capture(&cpu);
+        }
+        assert_eq!(cpu.v()[0], 5);
+        assert_eq!(rewind.len(), 3);
+        assert!(
+            rewind.memory() < 3 * 16,
+            "Only V0 changes: {}",
+            rewind.memory()
+        );
+
+        for expected in [4, 3, 2] {
+            assert!(rewind.step_back(&mut cpu).unwrap());
+            assert_eq!(cpu.v()[0], expected);
+        }
+        assert!(!rewind.step_back(&mut cpu).unwrap());
+        assert_eq!(cpu.v()[0], 2);
+        assert_eq!(rewind.memory(), 0);
+    }
+
+    #[test]
+    fn growing_ram() {
+        let mut cpu = Cpu::default();
+        // LD V0, 7; LD I, 0x800; LD [I], V0; EXIT
+        cpu.load_rom(&[0x60, 0x07, 0xA8, 0x00, 0xF0, 0x55, 0x00, 0xFD])
+            .unwrap();
+        let mut rewind = Rewind::default();
+        rewind.capture(&cpu);
+        assert!(rewind.current.as_ref().unwrap().len() < 0x2000 + 0x300);
+
+        for _ in 0..3 {
+            cpu.tick(&KeyState::new()).unwrap();
+            rewind.capture(&cpu);
+        }
+        assert_eq!(cpu.ram()[0x800], 7);
+
+        assert!(rewind.step_back(&mut cpu).unwrap());
+        assert!(rewind.step_back(&mut cpu).unwrap());
+        assert_eq!(cpu.ram()[0x800], 0);
+        assert_eq!(cpu.pc(), 0x202);
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut cpu = Cpu::default();
+        let mut rewind = Rewind::new(100, 8);
+
+        for value in 0..10 {
+            cpu.set_rpl_flags([value; 16]);
+            rewind.capture(&cpu);
+        }
+
+        // Each delta takes more than 16 bytes, so none fits
+        assert!(rewind.is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+//! Deterministic random source for CXKK.
+
+/// SplitMix64 generator: the same seed always gives the same bytes, and its
+/// state is small enough to be saved in snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { seed, state: seed }
+    }
+
+    /// Generator seeded from the system entropy.
+    pub fn from_entropy() -> Rng {
+        Rng::new(rand::random())
+    }
+
+    /// Seed the generator was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    /// Current state, which with the seed fully describes the generator.
+    pub(crate) fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub(crate) fn from_parts(seed: u64, state: u64) -> Rng {
+        Rng { seed, state }
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Rng {
+        Rng::from_entropy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reproducible() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let bytes: Vec<u8> = (0..16).map(|_| a.next_byte()).collect();
+
+        assert_eq!(bytes, (0..16).map(|_| b.next_byte()).collect::<Vec<_>>());
+        assert_ne!(Rng::new(43).next_byte(), Rng::new(42).next_byte());
+        assert_eq!(a.seed(), 42);
+    }
+}
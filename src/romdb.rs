@@ -0,0 +1,186 @@
+//! Database of known ROMs, embedded from `romdb.toml` and looked up by the
+//! SHA-1 of their bytes, telling the quirks and speed they were written for.
+
+use std::fmt::{self, Write as _};
+
+use crate::config::sha1;
+use crate::quirks::QuirksProfile;
+
+const DATABASE: &str = include_str!("romdb.toml");
+
+/// Machine a ROM was written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" => Some(Platform::Chip8),
+            "schip" => Some(Platform::SuperChip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// Quirks of the interpreters running the programs of the platform.
+    pub fn quirks(&self) -> QuirksProfile {
+        match self {
+            Platform::Chip8 | Platform::XoChip => QuirksProfile::Modern,
+            Platform::SuperChip => QuirksProfile::SuperChip,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Entry of the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: String,
+    pub author: Option<String>,
+    pub platform: Platform,
+    pub quirks: QuirksProfile,
+    /// Recommended instructions per 60Hz frame
+    pub tickrate: Option<u32>,
+    /// Description of the keypad
+    pub keys: Option<String>,
+}
+
+impl Metadata {
+    fn parse(entry: &toml::Value) -> Result<Metadata, String> {
+        let string = |key: &str| entry.get(key).and_then(toml::Value::as_str);
+        let platform = string("platform").unwrap_or("chip8");
+        let platform = Platform::from_name(platform)
+            .ok_or_else(|| format!("unknown platform `{}`", platform))?;
+        let quirks = match string("quirks") {
+            Some(name) => <QuirksProfile as clap::ValueEnum>::from_str(name, false)?,
+            None => platform.quirks(),
+        };
+        let tickrate = match entry.get("tickrate") {
+            Some(tickrate) => Some(
+                tickrate
+                    .as_integer()
+                    .and_then(|tickrate| u32::try_from(tickrate).ok())
+                    .ok_or("invalid tickrate")?,
+            ),
+            None => None,
+        };
+
+        Ok(Metadata {
+            title: string("title").ok_or("missing title")?.to_string(),
+            author: string("author").map(str::to_string),
+            platform,
+            quirks,
+            tickrate,
+            keys: string("keys").map(str::to_string),
+        })
+    }
+}
+
+/// Metadata of the ROM, if it is known.
+pub fn lookup(rom: &[u8]) -> Option<Metadata> {
+    let database: toml::Table = DATABASE.parse().expect("invalid ROM database");
+    let entry = database.get(&sha1(rom))?;
+    Some(Metadata::parse(entry).expect("invalid ROM database entry"))
+}
+
+/// Describe the ROM, with its metadata if it is known.
+pub fn info(rom: &[u8]) -> String {
+    let mut out = String::new();
+    let mut line = |name: &str, value: &dyn fmt::Display| {
+        writeln!(out, "{:<10}{}", format!("{}:", name), value).unwrap();
+    };
+
+    match lookup(rom) {
+        Some(metadata) => {
+            line("Title", &metadata.title);
+            line("Author", &metadata.author.as_deref().unwrap_or("unknown"));
+            line("Platform", &metadata.platform);
+            let quirks = <QuirksProfile as clap::ValueEnum>::to_possible_value(&metadata.quirks)
+                .map(|quirks| quirks.get_name().to_string())
+                .unwrap_or_default();
+            line("Quirks", &quirks);
+            if let Some(tickrate) = metadata.tickrate {
+                line("Tickrate", &format!("{} instructions per frame", tickrate));
+            }
+            if let Some(keys) = &metadata.keys {
+                line("Keys", keys);
+            }
+        }
+        None => line("Title", &"unknown, not in the ROM database"),
+    }
+    line("Size", &format!("{} bytes", rom.len()));
+    line("SHA-1", &sha1(rom));
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn bundled_roms() {
+        let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms");
+        for entry in fs::read_dir(roms).unwrap() {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "keymap")
+            {
+                continue;
+            }
+            let rom = fs::read(&path).unwrap();
+            assert!(lookup(&rom).is_some(), "{} is missing", path.display());
+        }
+    }
+
+    #[test]
+    fn entries() {
+        let database: toml::Table = DATABASE.parse().unwrap();
+        for (hash, entry) in &database {
+            assert_eq!(hash.len(), 40, "{}", hash);
+            assert!(Metadata::parse(entry).is_ok(), "{}", hash);
+        }
+    }
+
+    #[test]
+    fn metadata() {
+        let blitz = include_bytes!("../roms/BLITZ");
+        let metadata = lookup(blitz).unwrap();
+        assert_eq!(metadata.title, "Blitz");
+        assert_eq!(metadata.platform, Platform::Chip8);
+        assert_eq!(metadata.quirks, QuirksProfile::CosmacVip);
+        assert_eq!(
+            lookup(include_bytes!("../roms/BRIX")).unwrap().quirks,
+            QuirksProfile::Modern
+        );
+
+        assert_eq!(
+            info(blitz),
+            "Title:    Blitz\n\
+             Author:   David Winter\n\
+             Platform: CHIP-8\n\
+             Quirks:   cosmac-vip\n\
+             Keys:     5 drops a bomb\n\
+             Size:     391 bytes\n\
+             SHA-1:    6f6509f38220e057a7e32ebb22dd353c1078e3e7\n"
+        );
+        assert!(info(b"\x12\x00").starts_with("Title:    unknown"));
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+/// Delay and sound timers frequency
+pub const TIMER_HZ: u32 = 60;
+
+/// If the emulation falls further behind than this, it resynchronises with the
+/// clock instead of trying to catch up.
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+/// Emulation speed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Speed {
+    /// Instructions executed in each frame
+    pub instructions_per_frame: u32,
+    /// Frames per second, each one polling the input and refreshing the screen
+    pub frames_per_second: u32,
+}
+
+impl Speed {
+    pub fn new(instructions_per_frame: u32, frames_per_second: u32) -> Speed {
+        Speed {
+            instructions_per_frame,
+            frames_per_second: frames_per_second.max(1),
+        }
+    }
+
+    /// Speed running `cycles_per_second` instructions spread over `frames_per_second` frames.
+    pub fn from_cycles_per_second(cycles_per_second: u32, frames_per_second: u32) -> Speed {
+        let frames_per_second = frames_per_second.max(1);
+        Speed::new(cycles_per_second / frames_per_second, frames_per_second)
+    }
+
+    pub fn cycles_per_second(&self) -> u32 {
+        self.instructions_per_frame * self.frames_per_second
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.frames_per_second
+    }
+}
+
+impl Default for Speed {
+    fn default() -> Speed {
+        Speed::new(10, 60)
+    }
+}
+
+/// Paces frames and 60Hz timer ticks against a monotonic clock.
+///
+/// Deadlines are computed from the previous deadline rather than from the
+/// time the frame finished, so the time spent emulating does not accumulate
+/// as drift.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    speed: Speed,
+    next_frame: Duration,
+    next_timer: Duration,
+}
+
+impl Scheduler {
+    pub fn new(speed: Speed) -> Scheduler {
+        Scheduler {
+            speed,
+            ..Default::default()
+        }
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    /// Start counting from `now`.
+    pub fn reset(&mut self, now: Duration) {
+        self.next_frame = now;
+        self.next_timer = now + timer_period();
+    }
+
+    /// Number of 60Hz timer ticks elapsed up to `now` since the previous call.
+    pub fn timer_ticks(&mut self, now: Duration) -> u32 {
+        if now > self.next_timer + MAX_LAG {
+            self.next_timer = now;
+        }
+
+        let mut ticks = 0;
+        while now >= self.next_timer {
+            self.next_timer += timer_period();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    /// Time to wait from `now` until the next frame must start.
+    pub fn frame_delay(&mut self, now: Duration) -> Duration {
+        self.next_frame += self.speed.frame_duration();
+        if now > self.next_frame + MAX_LAG {
+            self.next_frame = now;
+        }
+        self.next_frame.saturating_sub(now)
+    }
+}
+
+fn timer_period() -> Duration {
+    Duration::from_secs(1) / TIMER_HZ
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_from_cycles_per_second() {
+        let speed = Speed::from_cycles_per_second(700, 60);
+
+        assert_eq!(speed.instructions_per_frame, 11);
+        assert_eq!(speed.cycles_per_second(), 660);
+    }
+
+    #[test]
+    fn timers_tick_at_60hz() {
+        let mut scheduler = Scheduler::new(Speed::new(10, 30));
+        scheduler.reset(Duration::ZERO);
+
+        let ticks: u32 = (1..=30)
+            .map(|frame| scheduler.timer_ticks(Duration::from_secs(1) * frame / 30))
+            .sum();
+
+        assert_eq!(ticks, 60);
+    }
+
+    #[test]
+    fn frame_delay_compensates_drift() {
+        let mut scheduler = Scheduler::new(Speed::new(10, 50));
+        scheduler.reset(Duration::ZERO);
+
+        // The first frame took 5ms to emulate
+        let delay = scheduler.frame_delay(Duration::from_millis(5));
+        assert_eq!(delay, Duration::from_millis(15));
+
+        // The second one overran its deadline: no waiting, the next one is shorter
+        let delay = scheduler.frame_delay(Duration::from_millis(45));
+        assert_eq!(delay, Duration::ZERO);
+        let delay = scheduler.frame_delay(Duration::from_millis(50));
+        assert_eq!(delay, Duration::from_millis(10));
+    }
+
+    #[test]
+    fn resynchronise_when_too_late() {
+        let mut scheduler = Scheduler::new(Speed::new(10, 60));
+        scheduler.reset(Duration::ZERO);
+
+        assert_eq!(scheduler.timer_ticks(Duration::from_secs(10)), 1);
+        assert_eq!(
+            scheduler.frame_delay(Duration::from_secs(10)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            scheduler.frame_delay(Duration::from_secs(10)),
+            Speed::default().frame_duration()
+        );
+    }
+}
@@ -1,60 +1,142 @@
 extern crate sdl2;
 
-use crate::constants::{HEIGHT, WIDTH};
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
 
+use crate::backend::Display;
+use crate::constants::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+use crate::palette::{self, Palette};
+use crate::vram::Vram;
+
+/// Settings of the SDL2 window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowOptions {
+    /// Initial window size, in window pixels per low resolution pixel
+    pub scale: u8,
+    /// Start in desktop fullscreen mode
+    pub fullscreen: bool,
+    /// Synchronise the presentation with the display refresh rate
+    pub vsync: bool,
+}
+
+impl Default for WindowOptions {
+    fn default() -> WindowOptions {
+        WindowOptions {
+            scale: 16,
+            fullscreen: false,
+            vsync: false,
+        }
+    }
+}
+
+/// Resizable window showing the screen scaled by an integer factor, keeping
+/// its aspect ratio.
+///
+/// Each frame is uploaded to a streaming texture as large as the high
+/// resolution screen, the low resolution only using its top left corner, and
+/// scaled by the renderer.
 pub struct Screen {
-    canvas: sdl2::render::Canvas<sdl2::video::Window>,
-    scale: usize,
+    canvas: Canvas<Window>,
+    texture: Texture,
+    palette: Palette,
+    /// Output size when the last frame was presented, to redraw after resizing
+    output_size: Option<(u32, u32)>,
 }
 
 impl Screen {
-    pub fn new(sdl_context: &sdl2::Sdl, scale: usize) -> Screen {
-        // let sdl_context = sdl2::init().unwrap();
+    pub fn new(sdl_context: &sdl2::Sdl, options: WindowOptions) -> Screen {
         let video_subsystem = sdl_context.video().unwrap();
+        let scale = options.scale.max(1) as u32;
 
-        let window = video_subsystem
-            .window("CHIP8", (scale * WIDTH) as u32, (scale * HEIGHT) as u32)
-            .position_centered()
-            .build()
-            .unwrap();
+        let mut window =
+            video_subsystem.window("CHIP8", scale * WIDTH as u32, scale * HEIGHT as u32);
+        window.position_centered().resizable();
+        if options.fullscreen {
+            window.fullscreen_desktop();
+        }
+        let window = window.build().unwrap();
 
-        let mut canvas = window.into_canvas().build().unwrap();
+        let mut canvas = window.into_canvas();
+        if options.vsync {
+            canvas = canvas.present_vsync();
+        }
+        let mut canvas = canvas.build().unwrap();
+        // Both resolutions have the same aspect ratio
+        canvas
+            .set_logical_size(WIDTH as u32, HEIGHT as u32)
+            .unwrap();
+        canvas.set_integer_scale(true).unwrap();
 
-        canvas.set_draw_color(Color::RGB(0, 255, 255));
-        canvas.clear();
-        canvas.present();
+        let texture = canvas
+            .texture_creator()
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                HIRES_WIDTH as u32,
+                HIRES_HEIGHT as u32,
+            )
+            .unwrap();
 
-        Screen { canvas, scale }
+        Screen {
+            canvas,
+            texture,
+            palette: Palette::default(),
+            output_size: None,
+        }
     }
 
-    pub fn tick(&mut self, buffer: &[[bool; 32]; 64]) {
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    fn upload(&mut self, vram: &Vram) -> Result<(), String> {
+        let area = Rect::new(0, 0, vram.width() as u32, vram.height() as u32);
+        let palette = &self.palette;
+        self.texture.with_lock(area, |buffer, pitch| {
+            for y in 0..vram.height() {
+                let row = &mut buffer[y * pitch..y * pitch + vram.width() * 3];
+                for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                    let color = palette.color(vram.color(x, y));
+                    pixel.copy_from_slice(&[color.r, color.g, color.b]);
+                }
+            }
+        })?;
+
+        self.canvas
+            .set_draw_color(sdl_color(self.palette.background()));
         self.canvas.clear();
-        self.draw(buffer);
+        self.canvas.copy(&self.texture, area, None)?;
         self.canvas.present();
+        self.output_size = self.canvas.output_size().ok();
+        Ok(())
     }
+}
 
-    // XXX: A bit coupled with vram layout
-    fn draw(&mut self, buffer: &[[bool; 32]; 64]) {
-        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-        for y in 0..HEIGHT {
-            let yy = y * self.scale;
-            for (x, _) in buffer.iter().enumerate().take(WIDTH) {
-                let xx = x * self.scale;
-                if buffer[x][y] {
-                    for i in 0..self.scale {
-                        for j in 0..self.scale {
-                            self.canvas
-                                .draw_point(sdl2::rect::Point::new(
-                                    (xx + i) as i32,
-                                    (yy + j) as i32,
-                                ))
-                                .unwrap();
-                        }
-                    }
-                }
-            }
+impl Display for Screen {
+    fn draw(&mut self, vram: &Vram) {
+        if let Err(err) = self.upload(vram) {
+            log::error!("Cannot draw the screen: {}", err);
+        }
+    }
+
+    fn needs_redraw(&mut self) -> bool {
+        self.canvas.output_size().ok() != self.output_size
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(err) = window.set_fullscreen(fullscreen) {
+            log::error!("Cannot toggle fullscreen: {}", err);
         }
     }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.output_size = None;
+    }
+}
+
+fn sdl_color(color: palette::Color) -> Color {
+    Color::RGB(color.r, color.g, color.b)
 }
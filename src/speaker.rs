@@ -0,0 +1,100 @@
+extern crate sdl2;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+use crate::audio::{Oscillator, Tone};
+use crate::backend::{Audio, NullAudio};
+
+const SAMPLE_RATE: i32 = 44100;
+
+impl AudioCallback for Oscillator {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.fill(out);
+    }
+}
+
+pub struct Speaker {
+    device: AudioDevice<Oscillator>,
+    playing: bool,
+}
+
+impl Speaker {
+    pub fn new(sdl_context: &sdl2::Sdl, tone: Tone) -> Result<Speaker, String> {
+        let audio_subsystem = sdl_context.audio()?;
+
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem
+            .open_playback(None, &spec, |spec| Oscillator::new(tone, spec.freq as u32))?;
+        device.resume();
+
+        Ok(Speaker {
+            device,
+            playing: false,
+        })
+    }
+
+    pub fn set_tone(&mut self, tone: Tone) {
+        self.device.lock().set_tone(tone);
+    }
+}
+
+impl Audio for Speaker {
+    fn set_playing(&mut self, playing: bool) {
+        // Avoid locking the audio thread every frame
+        if playing != self.playing {
+            self.playing = playing;
+            self.device.lock().set_playing(playing);
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        self.device.lock().set_pattern(pattern, pitch);
+    }
+}
+
+/// Audio of the SDL2 frontend: the speaker, or silence when there is no audio
+/// device to play on.
+pub enum SdlAudio {
+    Speaker(Speaker),
+    Null(NullAudio),
+}
+
+impl SdlAudio {
+    pub fn new(sdl_context: &sdl2::Sdl, tone: Tone) -> SdlAudio {
+        match Speaker::new(sdl_context, tone) {
+            Ok(speaker) => SdlAudio::Speaker(speaker),
+            Err(err) => {
+                log::warn!("Audio unavailable: {}", err);
+                SdlAudio::Null(NullAudio)
+            }
+        }
+    }
+
+    pub fn set_tone(&mut self, tone: Tone) {
+        if let SdlAudio::Speaker(speaker) = self {
+            speaker.set_tone(tone);
+        }
+    }
+}
+
+impl Audio for SdlAudio {
+    fn set_playing(&mut self, playing: bool) {
+        match self {
+            SdlAudio::Speaker(speaker) => speaker.set_playing(playing),
+            SdlAudio::Null(null) => null.set_playing(playing),
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        match self {
+            SdlAudio::Speaker(speaker) => speaker.set_pattern(pattern, pitch),
+            SdlAudio::Null(null) => null.set_pattern(pattern, pitch),
+        }
+    }
+}
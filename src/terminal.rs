@@ -0,0 +1,381 @@
+//! Terminal frontend, to play without a graphical display, e.g. over SSH.
+//!
+//! Each character cell shows two pixels stacked vertically with the upper half
+//! block `▀`, the top pixel in the foreground colour and the bottom one in the
+//! background colour.
+//!
+//! Most terminals only report key presses, repeated while the key is held down,
+//! so a key is considered released once it has not been reported for a while:
+//! [`RELEASE_DELAY`] until the first repeat, longer than the delay before the
+//! keyboard starts repeating, then [`REPEAT_RELEASE_DELAY`]. Terminals
+//! supporting the kitty keyboard protocol report the actual releases.
+//!
+//! Terminals report characters rather than keys, found back from the
+//! keyboard [`Layout`]. Keypad keys are reported as the characters they type.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, execute, terminal};
+
+use crate::backend::{Audio, Display, Input};
+use crate::keyboard::{Hotkey, Key, KeyEvent};
+use crate::keymap::{Keymap, Layout};
+use crate::palette::Palette;
+use crate::vram::Vram;
+
+/// Default time after a key press before it is released, unless repeated
+pub const RELEASE_DELAY: Duration = Duration::from_millis(500);
+/// Time after the last repeat of a key before it is released
+pub const REPEAT_RELEASE_DELAY: Duration = Duration::from_millis(100);
+
+/// Render the screen as rows of half blocks with 24-bit ANSI colours, starting
+/// from the top left corner of the terminal.
+pub fn render(vram: &Vram, palette: &Palette) -> String {
+    let mut out = String::from("\x1b[H");
+    let mut current = None;
+
+    for y in (0..vram.height()).step_by(2) {
+        for x in 0..vram.width() {
+            let colors = (vram.color(x, y), vram.color(x, y + 1));
+            if current != Some(colors) {
+                let (top, bottom) = (palette.color(colors.0), palette.color(colors.1));
+                write!(
+                    out,
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                    top.r, top.g, top.b, bottom.r, bottom.g, bottom.b
+                )
+                .unwrap();
+                current = Some(colors);
+            }
+            out.push('▀');
+        }
+        // Raw mode does not return the carriage on line feeds
+        out.push_str("\x1b[0m\r\n");
+        current = None;
+    }
+
+    out
+}
+
+/// The terminal switched to the alternate screen in raw mode, restored when dropped.
+pub struct TerminalScreen {
+    stdout: io::Stdout,
+    /// Resolution of the last frame and size of the terminal, to clear the
+    /// screen when either changes
+    size: Option<((usize, usize), (u16, u16))>,
+    palette: Palette,
+    /// Whether the terminal reports key releases
+    releases: bool,
+}
+
+impl TerminalScreen {
+    pub fn new() -> io::Result<TerminalScreen> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        Ok(TerminalScreen {
+            stdout,
+            size: None,
+            palette: Palette::default(),
+            releases,
+        })
+    }
+
+    /// Whether the terminal reports key releases, rather than only key presses.
+    pub fn reports_releases(&self) -> bool {
+        self.releases
+    }
+}
+
+impl Drop for TerminalScreen {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = execute!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl Display for TerminalScreen {
+    fn draw(&mut self, vram: &Vram) {
+        let size = (
+            (vram.width(), vram.height()),
+            terminal::size().unwrap_or_default(),
+        );
+        if self.size != Some(size) {
+            let _ = execute!(self.stdout, terminal::Clear(terminal::ClearType::All));
+            self.size = Some(size);
+        }
+        let _ = self
+            .stdout
+            .write_all(render(vram, &self.palette).as_bytes());
+        let _ = self.stdout.flush();
+    }
+
+    fn needs_redraw(&mut self) -> bool {
+        let terminal_size = terminal::size().unwrap_or_default();
+        self.size.is_none_or(|(_, size)| size != terminal_size)
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.size = None;
+    }
+}
+
+/// Keys held down, released once they have not been reported for a while.
+#[derive(Debug)]
+pub struct HeldKeys {
+    /// Event releasing each held key, with the time it was last reported and
+    /// whether it has been repeated
+    held: Vec<(KeyEvent, Instant, bool)>,
+    /// Time after a key press before it is released, unless repeated
+    delay: Duration,
+}
+
+impl Default for HeldKeys {
+    fn default() -> HeldKeys {
+        HeldKeys::new(RELEASE_DELAY)
+    }
+}
+
+impl HeldKeys {
+    pub fn new(delay: Duration) -> HeldKeys {
+        HeldKeys {
+            held: vec![],
+            delay,
+        }
+    }
+
+    /// Report a key as held down, returning whether it was newly pressed.
+    pub fn press(&mut self, release: KeyEvent, now: Instant) -> bool {
+        match self.held.iter_mut().find(|(event, _, _)| *event == release) {
+            Some((_, seen, repeated)) => {
+                *seen = now;
+                *repeated = true;
+                false
+            }
+            None => {
+                self.held.push((release, now, false));
+                true
+            }
+        }
+    }
+
+    /// Report a key as released, returning whether it was held down.
+    pub fn release(&mut self, release: KeyEvent) -> bool {
+        let len = self.held.len();
+        self.held.retain(|(event, _, _)| *event != release);
+        self.held.len() != len
+    }
+
+    /// Release the keys pressed and not repeated for the release delay, or not
+    /// repeated for [`REPEAT_RELEASE_DELAY`] since their last repeat.
+    pub fn expire(&mut self, now: Instant) -> Vec<KeyEvent> {
+        let repeat_delay = REPEAT_RELEASE_DELAY.min(self.delay);
+        let (expired, held) = self.held.drain(..).partition(|(_, seen, repeated)| {
+            let delay = if *repeated { repeat_delay } else { self.delay };
+            now.duration_since(*seen) >= delay
+        });
+        self.held = held;
+        expired.into_iter().map(|(event, _, _)| event).collect()
+    }
+}
+
+/// Keypad read from the terminal.
+pub struct TerminalKeyboard {
+    held: HeldKeys,
+    /// Whether releases are reported by the terminal rather than emulated
+    releases: bool,
+    keymap: Keymap,
+    layout: Layout,
+}
+
+impl TerminalKeyboard {
+    pub fn new(releases: bool) -> TerminalKeyboard {
+        TerminalKeyboard {
+            held: HeldKeys::default(),
+            releases,
+            keymap: Keymap::default(),
+            layout: Layout::default(),
+        }
+    }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    /// Time after a key press before it is released, unless the terminal
+    /// repeats it or reports the release.
+    pub fn set_release_delay(&mut self, delay: Duration) {
+        self.held = HeldKeys::new(delay);
+    }
+
+    fn map(&self, code: KeyCode) -> Option<Key> {
+        let name = match code {
+            KeyCode::Char(c) => self.layout.position(c)?,
+            KeyCode::Esc => "Escape",
+            KeyCode::Enter => "Return",
+            KeyCode::Tab => "Tab",
+            KeyCode::Up => "Up",
+            KeyCode::Down => "Down",
+            KeyCode::Left => "Left",
+            KeyCode::Right => "Right",
+            _ => return None,
+        };
+        self.keymap.key(name)
+    }
+
+    /// F1 to F4 save into the quick-save slots, F5 to F8 load them.
+    fn map_hotkey(code: KeyCode) -> Option<Hotkey> {
+        match code {
+            KeyCode::F(n @ 1..=4) => Some(Hotkey::SaveState(n)),
+            KeyCode::F(n @ 5..=8) => Some(Hotkey::LoadState(n - 4)),
+            _ => None,
+        }
+    }
+
+    fn key(&mut self, code: KeyCode, kind: KeyEventKind, events: &mut Vec<KeyEvent>) {
+        // Backspace rewinds while held down, like a keypad key
+        let (press, release) = match (self.map(code), code) {
+            (Some(key), _) => (KeyEvent::Pressed(key), KeyEvent::Released(key)),
+            (None, KeyCode::Backspace) => (
+                KeyEvent::Hotkey(Hotkey::Rewind(true)),
+                KeyEvent::Hotkey(Hotkey::Rewind(false)),
+            ),
+            (None, _) => {
+                if kind == KeyEventKind::Press {
+                    events.extend(TerminalKeyboard::map_hotkey(code).map(KeyEvent::Hotkey));
+                }
+                return;
+            }
+        };
+
+        match kind {
+            KeyEventKind::Release => {
+                if self.held.release(release) {
+                    events.push(release);
+                }
+            }
+            _ => {
+                if self.held.press(release, Instant::now()) {
+                    events.push(press);
+                }
+            }
+        }
+    }
+}
+
+impl Input for TerminalKeyboard {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        let mut events = vec![];
+
+        while let Ok(true) = event::poll(Duration::ZERO) {
+            match event::read() {
+                Ok(Event::Key(key))
+                    if key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL) =>
+                {
+                    events.push(KeyEvent::Pressed(Key::Exit));
+                }
+                Ok(Event::Key(key)) => self.key(key.code, key.kind, &mut events),
+                Ok(_) => (),
+                Err(_) => break,
+            }
+        }
+
+        if !self.releases {
+            events.extend(self.held.expire(Instant::now()));
+        }
+
+        events
+    }
+
+    fn set_keymap(&mut self, keymap: &Keymap) {
+        self.keymap = keymap.clone();
+    }
+}
+
+/// Buzzer ringing the terminal bell when the sound starts.
+#[derive(Debug, Default)]
+pub struct Bell {
+    playing: bool,
+}
+
+impl Audio for Bell {
+    fn set_playing(&mut self, playing: bool) {
+        if playing && !self.playing {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(b"\x07");
+            let _ = stdout.flush();
+        }
+        self.playing = playing;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_blocks() {
+        let mut vram = Vram::default();
+        vram.set(0, 0, true);
+        vram.set(1, 1, true);
+
+        let screen = render(&vram, &Palette::default());
+        let rows: Vec<&str> = screen.split("\r\n").collect();
+
+        assert_eq!(rows.len(), 16 + 1);
+        assert!(rows[0].starts_with(
+            "\x1b[H\x1b[38;2;255;255;255;48;2;0;0;0m▀\x1b[38;2;0;0;0;48;2;255;255;255m▀\
+             \x1b[38;2;0;0;0;48;2;0;0;0m▀▀"
+        ));
+        assert_eq!(rows[1].matches('▀').count(), 64);
+    }
+
+    #[test]
+    fn emulated_release() {
+        let start = Instant::now();
+        let mut held = HeldKeys::default();
+        let release = KeyEvent::Released(Key::Num5);
+
+        // Held until the keyboard starts repeating it
+        assert!(held.press(release, start));
+        assert_eq!(held.expire(start + RELEASE_DELAY / 2), vec![]);
+        assert!(!held.press(release, start + RELEASE_DELAY / 2));
+        let repeated = start + RELEASE_DELAY / 2 + REPEAT_RELEASE_DELAY / 2;
+        assert!(!held.press(release, repeated));
+        assert_eq!(held.expire(repeated + REPEAT_RELEASE_DELAY / 2), vec![]);
+        assert_eq!(held.expire(repeated + REPEAT_RELEASE_DELAY), vec![release]);
+
+        // Released after the delay when not repeated
+        let start = repeated + RELEASE_DELAY;
+        assert!(held.press(release, start));
+        assert_eq!(held.expire(start + RELEASE_DELAY * 9 / 10), vec![]);
+        assert_eq!(held.expire(start + RELEASE_DELAY), vec![release]);
+
+        let mut held = HeldKeys::new(Duration::from_millis(250));
+        assert!(held.press(release, start));
+        assert_eq!(
+            held.expire(start + Duration::from_millis(250)),
+            vec![release]
+        );
+    }
+}
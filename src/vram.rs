@@ -0,0 +1,216 @@
+use crate::constants::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+
+/// Video memory, either in low (64x32) or high (128x64) resolution.
+///
+/// Pixels are indexed by `(x, y)`, with `(0, 0)` at the top left corner.
+/// Each pixel holds one bit per XO-CHIP bitplane, giving 4 colours. Drawing,
+/// clearing and scrolling only affect the selected planes; plane 1 is the only
+/// one selected by default, matching CHIP8 and SUPER-CHIP.
+#[derive(Clone)]
+pub struct Vram {
+    hires: bool,
+    planes: u8,
+    pixels: [[u8; HIRES_HEIGHT]; HIRES_WIDTH],
+}
+
+/// Number of XO-CHIP bitplanes
+pub const PLANES: u8 = 2;
+
+impl Default for Vram {
+    fn default() -> Vram {
+        Vram {
+            hires: false,
+            planes: 0b01,
+            pixels: [[0; HIRES_HEIGHT]; HIRES_WIDTH],
+        }
+    }
+}
+
+impl Vram {
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switch resolution, clearing the screen.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [[0; HIRES_HEIGHT]; HIRES_WIDTH];
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            WIDTH
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            HEIGHT
+        }
+    }
+
+    /// Bitmask of the selected planes.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ((1 << PLANES) - 1);
+    }
+
+    /// Whether the pixel is lit in any plane.
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[x][y] != 0
+    }
+
+    /// Colour index of the pixel, one bit per plane.
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        self.pixels[x][y]
+    }
+
+    /// Light or clear the pixel in the selected planes.
+    pub fn set(&mut self, x: usize, y: usize, on: bool) {
+        if on {
+            self.pixels[x][y] |= self.planes;
+        } else {
+            self.pixels[x][y] &= !self.planes;
+        }
+    }
+
+    /// Flip the pixel in the given plane, returning whether it has been erased.
+    pub fn toggle(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let erased = self.pixels[x][y] & plane != 0;
+        self.pixels[x][y] ^= plane;
+        erased
+    }
+
+    /// Every pixel of the high resolution buffer, for save states.
+    pub(crate) fn raw(&self) -> &[[u8; HIRES_HEIGHT]; HIRES_WIDTH] {
+        &self.pixels
+    }
+
+    pub(crate) fn from_raw(
+        hires: bool,
+        planes: u8,
+        pixels: [[u8; HIRES_HEIGHT]; HIRES_WIDTH],
+    ) -> Vram {
+        Vram {
+            hires,
+            planes,
+            pixels,
+        }
+    }
+
+    /// Iterate over the pixels of the current resolution, column by column.
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        self.pixels[..self.width()]
+            .iter()
+            .flat_map(move |column| column[..self.height()].iter().map(|pixel| *pixel != 0))
+    }
+
+    /// Clear the selected planes.
+    pub fn clear(&mut self) {
+        for column in self.pixels.iter_mut() {
+            for pixel in column.iter_mut() {
+                *pixel &= !self.planes;
+            }
+        }
+    }
+
+    /// Scroll the screen down `n` pixels.
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    /// Scroll the screen up `n` pixels.
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n as isize));
+    }
+
+    /// Scroll the screen right `n` pixels.
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0);
+    }
+
+    /// Scroll the screen left `n` pixels.
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0);
+    }
+
+    /// Move the selected planes by `(dx, dy)`, filling the uncovered area with blank pixels.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let old = self.pixels;
+
+        for x in 0..width {
+            for y in 0..height {
+                let (xx, yy) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&xx) && (0..height).contains(&yy) {
+                    old[xx as usize][yy as usize] & self.planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[x as usize][y as usize];
+                *pixel = (*pixel & !self.planes) | moved;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scroll() {
+        let mut vram = Vram::default();
+        vram.set(0, 0, true);
+
+        vram.scroll_down(3);
+        assert!(vram.get(0, 3));
+        assert!(!vram.get(0, 0));
+
+        vram.scroll_right(4);
+        assert!(vram.get(4, 3));
+
+        vram.scroll_up(3);
+        vram.scroll_left(4);
+        assert!(vram.get(0, 0));
+
+        vram.scroll_left(4);
+        assert_eq!(vram.pixels().filter(|on| *on).count(), 0);
+    }
+
+    #[test]
+    fn resolution() {
+        let mut vram = Vram::default();
+        assert_eq!((vram.width(), vram.height()), (64, 32));
+
+        vram.set(1, 1, true);
+        vram.set_hires(true);
+
+        assert_eq!((vram.width(), vram.height()), (128, 64));
+        assert_eq!(vram.pixels().count(), 128 * 64);
+        assert!(!vram.get(1, 1), "Switching resolution clears the screen");
+    }
+
+    #[test]
+    fn planes() {
+        let mut vram = Vram::default();
+        vram.select_planes(0b11);
+        vram.set(0, 0, true);
+        vram.select_planes(0b10);
+        vram.set(1, 0, true);
+        assert_eq!(vram.color(0, 0), 0b11);
+        assert_eq!(vram.color(1, 0), 0b10);
+
+        vram.scroll_down(1);
+        vram.clear();
+        assert_eq!(vram.color(0, 0), 0b01, "Only plane 2 is cleared");
+        assert_eq!(vram.color(0, 1), 0);
+    }
+}
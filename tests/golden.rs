@@ -0,0 +1,166 @@
+//! Golden-image regression tests over the bundled ROMs.
+//!
+//! Each ROM runs headlessly for a number of frames, with a fixed seed and
+//! optional scripted key presses, and its screen is compared with a PBM image
+//! checked in under `tests/golden/`. After an intended change of behaviour,
+//! regenerate the images with:
+//!
+//! ```text
+//! UPDATE_GOLDEN=1 cargo test --no-default-features --test golden
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8::movie::{self, Frame, Movie};
+use chip8::{KeyState, Quirks, Speed, Vram};
+
+const SEED: u64 = 0xC8;
+
+/// A ROM run, `presses` holding a key for a range of frames.
+struct Case {
+    rom: &'static str,
+    frames: usize,
+    presses: &'static [Press],
+}
+
+struct Press {
+    key: u8,
+    from: usize,
+    to: usize,
+}
+
+const fn case(rom: &'static str, frames: usize) -> Case {
+    Case {
+        rom,
+        frames,
+        presses: &[],
+    }
+}
+
+const fn press(key: u8, from: usize, to: usize) -> Press {
+    Press { key, from, to }
+}
+
+const CASES: &[Case] = &[
+    case("test_opcode.ch8", 60),
+    case("IBM_Logo.ch8", 60),
+    case("15PUZZLE", 120),
+    case("BLINKY", 300),
+    case("BLITZ", 300),
+    Case {
+        rom: "BRIX",
+        frames: 300,
+        presses: &[press(0x6, 60, 120)],
+    },
+    case("CONNECT4", 120),
+    case("GUESS", 120),
+    case("HIDDEN", 120),
+    Case {
+        rom: "INVADERS",
+        frames: 400,
+        presses: &[press(0x5, 30, 40), press(0x6, 200, 260)],
+    },
+    case("KALEID", 120),
+    case("MAZE", 120),
+    case("MERLIN", 120),
+    case("MISSILE", 300),
+    Case {
+        rom: "PONG",
+        frames: 300,
+        presses: &[press(0x1, 30, 90)],
+    },
+    case("PONG2", 300),
+    case("PUZZLE", 120),
+    case("SYZYGY", 120),
+    case("TANK", 120),
+    Case {
+        rom: "TETRIS",
+        frames: 400,
+        presses: &[press(0x5, 60, 61), press(0x6, 100, 160)],
+    },
+    case("TICTAC", 120),
+    case("UFO", 300),
+    case("VBRIX", 300),
+    case("VERS", 300),
+    case("WIPEOFF", 300),
+];
+
+fn movie(case: &Case) -> Movie {
+    let mut movie = Movie::new(SEED, Quirks::default(), Speed::default());
+    for frame in 0..case.frames {
+        let mut keys = KeyState::new();
+        for press in case.presses {
+            if (press.from..press.to).contains(&frame) {
+                keys.set(press.key, true);
+            }
+        }
+        movie.push(Frame {
+            keys,
+            timer_ticks: 1,
+        });
+    }
+    movie
+}
+
+/// Plain PBM image, one character per pixel.
+fn pbm(vram: &Vram) -> String {
+    let mut out = format!("P1\n{} {}\n", vram.width(), vram.height());
+    for y in 0..vram.height() {
+        let row: Vec<&str> = (0..vram.width())
+            .map(|x| if vram.get(x, y) { "1" } else { "0" })
+            .collect();
+        out.push_str(&row.join(""));
+        out.push('\n');
+    }
+    out
+}
+
+fn golden_path(rom: &str) -> PathBuf {
+    let name = rom.trim_end_matches(".ch8");
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.pbm", name))
+}
+
+#[test]
+fn golden_images() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = vec![];
+
+    for case in CASES {
+        let rom = fs::read(root.join("roms").join(case.rom)).unwrap();
+        let vram =
+            movie::replay(&rom, &movie(case)).unwrap_or_else(|err| panic!("{}: {}", case.rom, err));
+        let actual = pbm(&vram);
+        let path = golden_path(case.rom);
+
+        if update {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(_) => {
+                let actual_path = root.join("target/golden").join(path.file_name().unwrap());
+                fs::create_dir_all(actual_path.parent().unwrap()).unwrap();
+                fs::write(&actual_path, &actual).unwrap();
+                failures.push(format!(
+                    "{}: screen differs, see {}",
+                    case.rom,
+                    actual_path.display()
+                ));
+            }
+            Err(err) => failures.push(format!("{}: {}: {}", case.rom, path.display(), err)),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Golden images mismatch (UPDATE_GOLDEN=1 to accept):\n{}",
+        failures.join("\n")
+    );
+}